@@ -1,27 +1,60 @@
-use std::{collections::HashMap, result, str::FromStr};
+use std::{collections::HashMap, fmt, process::ExitStatus, result, str::FromStr};
 
 use error_stack::{Report, Result, ResultExt};
 use serde::Deserialize;
 
-use crate::ctx_string::{Context, CtxString};
+use crate::{
+    ctx_string::{find_cycle, Context, CtxParseError, CtxString},
+    fs::{parse_size, MountRequirement, SpaceRequirement},
+    rsync::{Outcome, DEFAULT_ACCEPT_CODES, DEFAULT_WARN_CODES},
+};
 
-use self::error::{
-    BackupBuildError, ConfigBuildError, ConfigParseError, LogBuildError, OutLvlParseError,
+use self::{
+    error::{
+        BackupBuildError, ConfigBuildError, ConfigParseError, LogBuildError, MethodBuildError,
+        OutLvlParseError, SizeParseError,
+    },
+    keys::{key_path, ConfigKey, KeySpans},
+    settings::SettingsConfig,
 };
 
+pub(crate) use self::{keys::UnknownKey, restore::Restore, select::Selection, settings::Settings};
+
+mod check;
 mod error;
+mod keys;
 mod preview;
+mod prune;
+mod restore;
 mod run;
+mod select;
+mod settings;
+mod verify;
 
 // --- Constants
 
 const DEFAULT_OUT_SAVE: &str = "output.log";
 const DEFAULT_ERR_SAVE: &str = "errors.log";
 const DEFAULT_LOG_FORMAT: &str = "${log}";
+const DEFAULT_SNAPSHOT_NAME: &str = "%Y-%m-%d_%H%M";
+/// Name of the symlink pointing to the latest successful snapshot
+const LATEST_SNAPSHOT: &str = "latest";
 
+const TEMPLATE_SOURCE: &str = "[Source]";
+const TEMPLATE_TARGET: &str = "[Target]";
+
+const NAME_BINDING: &str = "name";
 const SOURCE_BINDING: &str = "source";
 const TARGET_BINDING: &str = "target";
 const LOG_BINDING: &str = "log";
+const STATUS_BINDING: &str = "status";
+const STATUS_MEANING_BINDING: &str = "status_meaning";
+const OUTCOME_BINDING: &str = "outcome";
+const SNAPSHOT_BINDING: &str = "snapshot";
+const STATS_BINDING: &str = "stats";
+const LINE_BINDING: &str = "line";
+const LINE_NO_BINDING: &str = "line_no";
+const STREAM_BINDING: &str = "stream";
 
 // --- Merge
 
@@ -45,6 +78,11 @@ where
 
 #[derive(Debug, Deserialize)]
 pub(crate) struct Config {
+    #[serde(skip)]
+    unknown_keys: Vec<UnknownKey>,
+    #[serde(skip)]
+    spans: KeySpans,
+    settings: Option<SettingsConfig>,
     template: Option<Template>,
     run: Option<Vec<BackupConfig>>,
     variables: Option<HashMap<String, String>>,
@@ -53,48 +91,193 @@ pub(crate) struct Config {
 impl FromStr for Config {
     type Err = Report<ConfigParseError>;
 
+    /// Parse a config, rejecting unknown keys unless `settings.strict` is disabled
     fn from_str(s: &str) -> result::Result<Self, Self::Err> {
-        toml::from_str(s).change_context(ConfigParseError)
+        let (mut config, unknown_keys) = Config::parse(s)?;
+        if config.strict() && !unknown_keys.is_empty() {
+            let report = unknown_keys
+                .iter()
+                .fold(Report::new(ConfigParseError), |report, key| {
+                    report.attach_printable(key.to_string())
+                });
+            return Err(report.attach_printable(
+                "Set `strict = false` in [settings] to only warn about unknown keys",
+            ));
+        }
+        config.unknown_keys = unknown_keys;
+        Ok(config)
     }
 }
 
 impl Config {
-    pub fn build(self) -> Result<(Context, Vec<Backup>), ConfigBuildError> {
+    /// Parse a config, collecting all keys backer doesn't know
+    pub fn parse(s: &str) -> Result<(Config, Vec<UnknownKey>), ConfigParseError> {
+        let mut unknown = Vec::new();
+        let mut config: Config = serde_ignored::deserialize(toml::Deserializer::new(s), |path| {
+            unknown.push(key_path(&path))
+        })
+        .change_context(ConfigParseError)?;
+
+        config.spans = KeySpans::new(s);
+        let unknown = unknown
+            .into_iter()
+            .map(|path| UnknownKey::new(path, &config.spans))
+            .collect();
+        Ok((config, unknown))
+    }
+
+    /// The `[settings]` section, which builds even if the backups don't
+    pub fn settings(&self) -> Settings {
+        self.settings.clone().unwrap_or_default().build()
+    }
+
+    /// Whether unknown keys are an error instead of a warning
+    pub fn strict(&self) -> bool {
+        self.settings().strict
+    }
+
+    /// Unknown keys that were tolerated, because `strict` is disabled
+    pub fn unknown_keys(&self) -> &[UnknownKey] {
+        &self.unknown_keys
+    }
+
+    /// Positions of all keys, for pointing errors into the config file
+    pub fn key_spans(&self) -> &KeySpans {
+        &self.spans
+    }
+
+    pub fn build(self) -> Result<(Settings, Context, Vec<Backup>), ConfigBuildError> {
+        let settings = self.settings();
+        let shared_context = self.build_context()?;
+        let runners = self
+            .build_backups()
+            .into_iter()
+            .collect::<result::Result<Vec<Backup>, _>>()
+            .map_err(|e| combine(e).change_context(ConfigBuildError))?;
+
+        let problems = validate_backups(&shared_context, runners.iter().enumerate());
+        if !problems.is_empty() {
+            return Err(combine(problems));
+        }
+
+        Ok((settings, shared_context, runners))
+    }
+
+    /// The `[variables]` shared by all backups
+    pub fn build_context(&self) -> Result<Context, ConfigBuildError> {
         let shared_context = self
             .variables
             .iter()
             .flatten()
-            .map(|(key, val)| Ok((key.to_owned(), CtxString::new(val)?)))
+            .map(|(key, val)| Ok((key.to_owned(), parse_key(val, key)?)))
             .collect::<Result<Context, _>>()
+            .map_err(|e| self.spans.locate(e, &["variables"]))
             .change_context(error::ConfigBuildError)?;
 
-        let runners = self
-            .run
-            .into_iter()
-            .flatten()
-            .map(|bcn| bcn.merge(self.template.clone()).build())
-            .collect::<Result<Vec<Backup>, _>>()
-            .change_context(error::ConfigBuildError)?;
+        if let Some(cycle) = find_cycle(&shared_context) {
+            return Err(Report::new(ConfigBuildError)
+                .attach_printable(format!("Recursive variable: {}", cycle.join(" -> "))));
+        }
+        Ok(shared_context)
+    }
+
+    /// Build every `[[run]]` on its own,
+    /// so a broken backup doesn't hide the problems of the others
+    pub fn build_backups(&self) -> Vec<result::Result<Backup, Vec<Report<BackupBuildError>>>> {
+        Vec::from_iter(self.run.iter().flatten().enumerate().map(|(i, bcn)| {
+            let tables = [&format!("run[{}]", i), "template"];
+            bcn.clone()
+                .merge(self.template.clone())
+                .build()
+                .map_err(|e| Vec::from_iter(e.into_iter().map(|e| self.spans.locate(e, &tables))))
+        }))
+    }
 
-        Ok((shared_context, runners))
+    /// Build a backup out of the template alone,
+    /// with placeholders for `source` and `target`
+    pub fn build_template(&self) -> Result<Backup, ConfigBuildError> {
+        let template = self.template.clone();
+        BackupConfig {
+            source: TEMPLATE_SOURCE.to_owned(),
+            target: TEMPLATE_TARGET.to_owned(),
+            ..Default::default()
+        }
+        .merge(template)
+        .build()
+        .map_err(|e| {
+            combine(e.into_iter().map(|e| self.spans.locate(e, &["template"])))
+                .change_context(ConfigBuildError)
+        })
+    }
+}
+
+/// Problems between backups that built on their own:
+/// names used more than once and variables of a backup that recurse.
+/// Each backup comes with its position in the config.
+pub(crate) fn validate_backups<'a>(
+    shared_context: &Context,
+    runners: impl IntoIterator<Item = (usize, &'a Backup)>,
+) -> Vec<Report<ConfigBuildError>> {
+    let mut problems = Vec::new();
+    let mut names = Vec::new();
+    for (i, runner) in runners {
+        if let Some(name) = runner.name() {
+            if names.contains(&name) {
+                problems.push(
+                    Report::new(ConfigBuildError)
+                        .attach_printable(format!("Backup name {:?} is used more than once", name)),
+                );
+            }
+            names.push(name);
+        }
+        if let Some(cycle) = find_cycle(&runner.context(shared_context)) {
+            problems.push(
+                Report::new(ConfigBuildError)
+                    .attach_printable(format!("Recursive variable: {}", cycle.join(" -> ")))
+                    .attach_printable(format!("Found while building backup {}", i + 1)),
+            );
+        }
     }
+    problems
 }
 
 #[derive(Clone, Debug, Deserialize)]
 struct Template {
+    create_target: Option<bool>,
+    require_mount: Option<bool>,
+    target_uuid: Option<String>,
+    target_label: Option<String>,
+    min_free: Option<SizeConfig>,
+    min_free_percent: Option<f64>,
+    estimate_transfer: Option<bool>,
     output: Option<OutLvlConfig>,
+    on_failure: Option<FailurePolicy>,
     method: Option<MethodConfig>,
+    retention: Option<RetentionConfig>,
     exclude: Option<Vec<String>>,
+    tags: Option<Vec<String>>,
     log: Option<LogConfig>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize)]
 struct BackupConfig {
+    name: Option<String>,
+    description: Option<String>,
     source: String,
     target: String,
+    create_target: Option<bool>,
+    require_mount: Option<bool>,
+    target_uuid: Option<String>,
+    target_label: Option<String>,
+    min_free: Option<SizeConfig>,
+    min_free_percent: Option<f64>,
+    estimate_transfer: Option<bool>,
     output: Option<OutLvlConfig>,
+    on_failure: Option<FailurePolicy>,
     method: Option<MethodConfig>,
+    retention: Option<RetentionConfig>,
     exclude: Option<Vec<String>>,
+    tags: Option<Vec<String>>,
     log: Option<LogConfig>,
 }
 
@@ -102,11 +285,23 @@ impl Merge<Option<Template>> for BackupConfig {
     fn merge(self, fallback: Option<Template>) -> Self {
         match fallback {
             Some(template) => BackupConfig {
+                name: self.name,
+                description: self.description,
                 source: self.source,
                 target: self.target,
+                create_target: self.create_target.or(template.create_target),
+                require_mount: self.require_mount.or(template.require_mount),
+                target_uuid: self.target_uuid.or(template.target_uuid),
+                target_label: self.target_label.or(template.target_label),
+                min_free: self.min_free.or(template.min_free),
+                min_free_percent: self.min_free_percent.or(template.min_free_percent),
+                estimate_transfer: self.estimate_transfer.or(template.estimate_transfer),
                 output: self.output.or(template.output),
+                on_failure: self.on_failure.or(template.on_failure),
                 method: self.method.merge(template.method),
+                retention: self.retention.merge(template.retention),
                 exclude: self.exclude.or(template.exclude),
+                tags: self.tags.or(template.tags),
                 log: self.log.merge(template.log),
             },
             None => self,
@@ -115,30 +310,92 @@ impl Merge<Option<Template>> for BackupConfig {
 }
 
 impl BackupConfig {
-    fn build(&self) -> Result<Backup, BackupBuildError> {
+    /// Build a backup, returning an error for every key that failed to build
+    fn build(&self) -> result::Result<Backup, Vec<Report<BackupBuildError>>> {
+        let mut problems = Vec::new();
+        let source = parse_key(&self.source, "source")
+            .change_context(BackupBuildError)
+            .map_err(|e| problems.push(e))
+            .ok();
+        let target = parse_key(&self.target, "target")
+            .change_context(BackupBuildError)
+            .map_err(|e| problems.push(e))
+            .ok();
+        let min_free = match &self.min_free {
+            Some(size) => size
+                .build()
+                .attach_printable(ConfigKey(String::from("min_free")))
+                .change_context(BackupBuildError)
+                .map(Some)
+                .map_err(|e| problems.push(e))
+                .ok(),
+            None => Some(None),
+        };
+        let output = match &self.output {
+            Some(o) => o
+                .build()
+                .attach_printable(ConfigKey(String::from("output")))
+                .change_context(BackupBuildError)
+                .map_err(|e| problems.push(e))
+                .ok(),
+            None => Some(OutLvl::default()),
+        };
+        let method = match &self.method {
+            Some(m) => m
+                .build()
+                .change_context(BackupBuildError)
+                .map_err(|e| problems.push(e))
+                .ok(),
+            None => Some(Method::default()),
+        };
+        let exclude = Vec::from_iter(self.exclude.iter().flatten().enumerate().map(|(i, s)| {
+            parse_key(s, &format!("exclude[{}]", i))
+                .change_context(BackupBuildError)
+                .map_err(|e| problems.push(e))
+                .ok()
+        }));
+        let log = match &self.log {
+            Some(l) => l
+                .build()
+                .map_err(|e| {
+                    problems.extend(e.into_iter().map(|e| e.change_context(BackupBuildError)))
+                })
+                .ok(),
+            None => Some(Log::default()),
+        };
+
+        // Every missing value left a problem behind
+        let (Some(source), Some(target), Some(min_free), Some(output), Some(method), Some(log)) =
+            (source, target, min_free, output, method, log)
+        else {
+            return Err(problems);
+        };
+        let Some(exclude) = exclude.into_iter().collect() else {
+            return Err(problems);
+        };
         Ok(Backup {
-            source: CtxString::new(&self.source).change_context(BackupBuildError)?,
-            target: CtxString::new(&self.target).change_context(BackupBuildError)?,
-            output: match &self.output {
-                Some(o) => o.build().change_context(BackupBuildError)?,
-                None => OutLvl::default(),
+            name: self.name.clone(),
+            description: self.description.clone(),
+            source,
+            target,
+            create_target: self.create_target.unwrap_or_default(),
+            mount: MountRequirement {
+                mounted: self.require_mount.unwrap_or_default(),
+                uuid: self.target_uuid.clone(),
+                label: self.target_label.clone(),
             },
-            method: match &self.method {
-                Some(m) => m.build(),
-                None => Method::default(),
-            },
-            exclude: match &self.exclude {
-                Some(e) => e
-                    .iter()
-                    .map(|s| CtxString::new(s))
-                    .collect::<Result<Vec<_>, _>>()
-                    .change_context(BackupBuildError)?,
-                None => Vec::with_capacity(0),
-            },
-            log: match &self.log {
-                Some(l) => l.build().change_context(BackupBuildError)?,
-                None => Log::default(),
+            space: SpaceRequirement {
+                min_free,
+                min_free_percent: self.min_free_percent,
+                estimate: self.estimate_transfer.unwrap_or_default(),
             },
+            output,
+            on_failure: self.on_failure.unwrap_or_default(),
+            method,
+            retention: self.retention.clone().unwrap_or_default().build(),
+            exclude,
+            tags: self.tags.clone().unwrap_or_default(),
+            log,
         })
     }
 }
@@ -157,13 +414,35 @@ impl OutLvlConfig {
             OutLvlConfig::Numeric(0) => Ok(OutLvl::Quiet),
             OutLvlConfig::Numeric(1) => Ok(OutLvl::Default),
             OutLvlConfig::Numeric(2) => Ok(OutLvl::Verbose),
+            OutLvlConfig::Numeric(3) => Ok(OutLvl::Progress),
             OutLvlConfig::Numeric(n) => Err(Report::new(OutLvlParseError)
                 .attach_printable(format!("{} is not a valid value for output", n))),
         }
     }
 }
 
-#[derive(Clone, Default, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+enum SizeConfig {
+    Bytes(u64),
+    Text(String),
+}
+
+impl SizeConfig {
+    fn build(&self) -> Result<u64, SizeParseError> {
+        match self {
+            SizeConfig::Bytes(bytes) => Ok(*bytes),
+            SizeConfig::Text(text) => parse_size(text).ok_or_else(|| {
+                Report::new(SizeParseError).attach_printable(format!(
+                    "{:?} is not a valid size, try something like \"20GiB\"",
+                    text
+                ))
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Default, Debug, Deserialize, PartialEq, Eq)]
 enum OutLvl {
     #[serde(rename(deserialize = "quiet"))]
     Quiet,
@@ -174,31 +453,199 @@ enum OutLvl {
 
     #[serde(rename(deserialize = "verbose"))]
     Verbose,
+
+    /// Show a single progress line instead of rsync's output
+    #[serde(rename(deserialize = "progress"))]
+    Progress,
+}
+
+/// What to do with the remaining backups once a backup failed
+#[derive(Clone, Copy, Default, Debug, Deserialize, PartialEq, Eq)]
+pub(crate) enum FailurePolicy {
+    /// Run the remaining backups anyway
+    #[serde(rename(deserialize = "continue"))]
+    #[default]
+    Continue,
+
+    /// Stop immediately, without printing a summary
+    #[serde(rename(deserialize = "abort"))]
+    Abort,
+
+    /// Skip the remaining backups, but still print a summary
+    #[serde(rename(deserialize = "abort_remaining"))]
+    AbortRemaining,
+}
+
+impl fmt::Display for FailurePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FailurePolicy::Continue => write!(f, "continue"),
+            FailurePolicy::Abort => write!(f, "abort"),
+            FailurePolicy::AbortRemaining => write!(f, "abort_remaining"),
+        }
+    }
+}
+
+/// What came of running backups one after another
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Runs<T> {
+    /// What each backup that ran returned, in order
+    pub results: Vec<T>,
+    /// How many backups didn't run, because an earlier one failed
+    pub skipped: usize,
+    /// Whether a backup failed with `on_failure = "abort"`,
+    /// which stops everything without a summary
+    pub aborted: bool,
+    /// Whether any backup failed
+    pub failed: bool,
+}
+
+impl<T> Runs<T> {
+    /// Whether every backup ran and none of them failed
+    pub fn succeeded(&self) -> bool {
+        !self.failed && self.skipped == 0
+    }
+}
+
+/// Run `backups` in order until one fails with an `on_failure` policy that stops the others.
+/// `run` returns what a backup returned and whether it failed.
+pub(crate) fn run_in_order<B, T>(
+    backups: &[B],
+    on_failure: impl Fn(&B) -> FailurePolicy,
+    mut run: impl FnMut(usize, &B) -> (T, bool),
+) -> Runs<T> {
+    let mut runs = Runs {
+        results: Vec::with_capacity(backups.len()),
+        skipped: 0,
+        aborted: false,
+        failed: false,
+    };
+    for (i, backup) in backups.iter().enumerate() {
+        let (result, failed) = run(i, backup);
+        runs.results.push(result);
+        if !failed {
+            continue;
+        }
+        runs.failed = true;
+        match on_failure(backup) {
+            FailurePolicy::Continue => (),
+            FailurePolicy::AbortRemaining => break,
+            FailurePolicy::Abort => {
+                runs.aborted = true;
+                break;
+            }
+        }
+    }
+    runs.skipped = backups.len() - runs.results.len();
+    runs
+}
+
+/// How a backup is laid out in its target
+#[derive(Clone, Copy, Default, Debug, Deserialize, PartialEq, Eq)]
+pub(crate) enum Mode {
+    /// Keep the target an exact copy of the source
+    #[serde(rename(deserialize = "mirror"))]
+    #[default]
+    Mirror,
+
+    /// Write every run into its own directory of the target,
+    /// hard-linking files unchanged since the latest snapshot
+    #[serde(rename(deserialize = "snapshot"))]
+    Snapshot,
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mode::Mirror => write!(f, "mirror"),
+            Mode::Snapshot => write!(f, "snapshot"),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
 struct MethodConfig {
+    mode: Option<Mode>,
+    snapshot_name: Option<String>,
+    stats: Option<bool>,
+    itemize: Option<bool>,
     sudo: Option<bool>,
     delete: Option<bool>,
     dry_run: Option<bool>,
+    accept_codes: Option<Vec<i32>>,
+    warn_codes: Option<Vec<i32>>,
 }
 
 impl Merge<MethodConfig> for MethodConfig {
     fn merge(self, fallback: MethodConfig) -> Self {
         MethodConfig {
+            mode: self.mode.or(fallback.mode),
+            snapshot_name: self.snapshot_name.or(fallback.snapshot_name),
+            stats: self.stats.or(fallback.stats),
+            itemize: self.itemize.or(fallback.itemize),
             sudo: self.sudo.or(fallback.sudo),
             delete: self.delete.or(fallback.delete),
             dry_run: self.dry_run.or(fallback.dry_run),
+            accept_codes: self.accept_codes.or(fallback.accept_codes),
+            warn_codes: self.warn_codes.or(fallback.warn_codes),
         }
     }
 }
 
 impl MethodConfig {
-    fn build(&self) -> Method {
-        Method {
+    fn build(&self) -> Result<Method, MethodBuildError> {
+        Ok(Method {
+            mode: self.mode.unwrap_or_default(),
+            snapshot_name: match &self.snapshot_name {
+                Some(s) => parse_key(s, "method.snapshot_name").change_context(MethodBuildError)?,
+                None => Method::default().snapshot_name,
+            },
+            stats: self.stats.unwrap_or_default(),
+            itemize: self.itemize.unwrap_or_default(),
             sudo: self.sudo.unwrap_or_default(),
             delete: self.delete.unwrap_or_default(),
             dry_run: self.dry_run.unwrap_or_default(),
+            accept_codes: match &self.accept_codes {
+                Some(c) => c.clone(),
+                None => Method::default().accept_codes,
+            },
+            warn_codes: match &self.warn_codes {
+                Some(c) => c.clone(),
+                None => Method::default().warn_codes,
+            },
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+struct RetentionConfig {
+    keep_last: Option<usize>,
+    keep_daily: Option<usize>,
+    keep_weekly: Option<usize>,
+    keep_monthly: Option<usize>,
+    keep_yearly: Option<usize>,
+}
+
+impl Merge<RetentionConfig> for RetentionConfig {
+    fn merge(self, fallback: RetentionConfig) -> Self {
+        RetentionConfig {
+            keep_last: self.keep_last.or(fallback.keep_last),
+            keep_daily: self.keep_daily.or(fallback.keep_daily),
+            keep_weekly: self.keep_weekly.or(fallback.keep_weekly),
+            keep_monthly: self.keep_monthly.or(fallback.keep_monthly),
+            keep_yearly: self.keep_yearly.or(fallback.keep_yearly),
+        }
+    }
+}
+
+impl RetentionConfig {
+    fn build(self) -> Retention {
+        Retention {
+            keep_last: self.keep_last,
+            keep_daily: self.keep_daily,
+            keep_weekly: self.keep_weekly,
+            keep_monthly: self.keep_monthly,
+            keep_yearly: self.keep_yearly,
         }
     }
 }
@@ -208,7 +655,11 @@ struct LogConfig {
     append: Option<bool>,
     stderr: Option<String>,
     stdout: Option<String>,
+    changes: Option<String>,
+    combined: Option<String>,
+    split: Option<bool>,
     format: Option<String>,
+    line_format: Option<String>,
 }
 
 impl Merge<LogConfig> for LogConfig {
@@ -217,56 +668,182 @@ impl Merge<LogConfig> for LogConfig {
             append: self.append.or(fallback.append),
             stderr: self.stderr.or(fallback.stderr),
             stdout: self.stdout.or(fallback.stdout),
+            changes: self.changes.or(fallback.changes),
+            combined: self.combined.or(fallback.combined),
+            split: self.split.or(fallback.split),
             format: self.format.or(fallback.format),
+            line_format: self.line_format.or(fallback.line_format),
         }
     }
 }
 
 impl LogConfig {
-    fn build(&self) -> Result<Log, LogBuildError> {
+    /// Build the log settings, returning an error for every format string that can't be parsed
+    fn build(&self) -> result::Result<Log, Vec<Report<LogBuildError>>> {
+        let mut problems = Vec::new();
+        let mut parse = |s: &Option<String>, key: &str| match s {
+            Some(s) => parse_key(s, key)
+                .change_context(LogBuildError)
+                .map(Some)
+                .map_err(|e| problems.push(e))
+                .ok(),
+            None => Some(None),
+        };
+        let stderr = parse(&self.stderr, "log.stderr");
+        let stdout = parse(&self.stdout, "log.stdout");
+        let changes = parse(&self.changes, "log.changes");
+        let combined = parse(&self.combined, "log.combined");
+        let format = parse(&self.format, "log.format");
+        let line_format = parse(&self.line_format, "log.line_format");
+
+        let (
+            Some(stderr),
+            Some(stdout),
+            Some(changes),
+            Some(combined),
+            Some(format),
+            Some(line_format),
+        ) = (stderr, stdout, changes, combined, format, line_format)
+        else {
+            return Err(problems);
+        };
         Ok(Log {
             append: self.append.unwrap_or_default(),
-            stderr: match &self.stderr {
-                Some(s) => CtxString::new(s).change_context(LogBuildError)?,
-                None => Log::default().stderr,
-            },
-            stdout: match &self.stdout {
-                Some(s) => CtxString::new(s).change_context(LogBuildError)?,
-                None => Log::default().stdout,
-            },
-            format: match &self.format {
-                Some(s) => CtxString::new(s).change_context(LogBuildError)?,
-                None => Log::default().format,
-            },
+            stderr: stderr.unwrap_or_else(|| Log::default().stderr),
+            stdout: stdout.unwrap_or_else(|| Log::default().stdout),
+            changes,
+            combined,
+            split: self.split.unwrap_or(true),
+            format: format.unwrap_or_else(|| Log::default().format),
+            line_format,
         })
     }
 }
 
+/// Join the errors of several keys into one report, there has to be at least one
+fn combine<C>(problems: impl IntoIterator<Item = Report<C>>) -> Report<C> {
+    problems
+        .into_iter()
+        .reduce(|mut first, problem| {
+            first.extend_one(problem);
+            first
+        })
+        .expect("at least one problem")
+}
+
+/// Parse a format string, naming the key it was set by in case of errors
+fn parse_key(s: &str, key: &str) -> Result<CtxString, CtxParseError> {
+    CtxString::new(s).attach_printable_lazy(|| ConfigKey(key.to_owned()))
+}
+
 // --- Finalized Runner
 
 #[derive(Debug)]
 pub(crate) struct Backup {
+    name: Option<String>,
+    description: Option<String>,
     source: CtxString,
     target: CtxString,
+    create_target: bool,
+    mount: MountRequirement,
+    space: SpaceRequirement,
     output: OutLvl,
+    on_failure: FailurePolicy,
     method: Method,
+    retention: Retention,
     exclude: Vec<CtxString>,
+    tags: Vec<String>,
     log: Log,
 }
 
-#[derive(Debug, Default)]
+impl Backup {
+    pub(crate) fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub(crate) fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub(crate) fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Label used in headers and summaries, like `1/3 (home)`
+    pub(crate) fn label(&self, num: &str) -> String {
+        match &self.name {
+            Some(name) => format!("{} ({})", num, name),
+            None => num.to_owned(),
+        }
+    }
+
+    pub(crate) fn on_failure(&self) -> FailurePolicy {
+        self.on_failure
+    }
+
+    /// Classify how rsync exited, based on `accept_codes` and `warn_codes`
+    pub(crate) fn classify(&self, status: &ExitStatus) -> Outcome {
+        match status.code() {
+            Some(c) if self.method.accept_codes.contains(&c) => Outcome::Ok,
+            Some(c) if self.method.warn_codes.contains(&c) => Outcome::Warn,
+            _ => Outcome::Fail,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
 struct Method {
+    mode: Mode,
+    snapshot_name: CtxString,
+    stats: bool,
+    itemize: bool,
     sudo: bool,
     delete: bool,
     dry_run: bool,
+    accept_codes: Vec<i32>,
+    warn_codes: Vec<i32>,
 }
 
-#[derive(Debug)]
+impl Default for Method {
+    fn default() -> Self {
+        Method {
+            mode: Mode::default(),
+            snapshot_name: CtxString::new(DEFAULT_SNAPSHOT_NAME).unwrap(),
+            stats: false,
+            itemize: false,
+            sudo: false,
+            delete: false,
+            dry_run: false,
+            accept_codes: DEFAULT_ACCEPT_CODES.to_vec(),
+            warn_codes: DEFAULT_WARN_CODES.to_vec(),
+        }
+    }
+}
+
+/// How many snapshots to keep when pruning, unset rules keep nothing
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Retention {
+    keep_last: Option<usize>,
+    keep_daily: Option<usize>,
+    keep_weekly: Option<usize>,
+    keep_monthly: Option<usize>,
+    keep_yearly: Option<usize>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
 struct Log {
     append: bool,
     stderr: CtxString,
     stdout: CtxString,
+    /// Where to list every file a run changed
+    changes: Option<CtxString>,
+    /// Where to log stdout and stderr together, in the order they arrive
+    combined: Option<CtxString>,
+    /// Whether to log stdout and stderr to separate files
+    split: bool,
     format: CtxString,
+    /// Format of every line of output, instead of the line itself
+    line_format: Option<CtxString>,
 }
 
 impl Default for Log {
@@ -275,7 +852,14 @@ impl Default for Log {
             append: false,
             stderr: CtxString::new(DEFAULT_ERR_SAVE).unwrap(),
             stdout: CtxString::new(DEFAULT_OUT_SAVE).unwrap(),
+            changes: None,
+            combined: None,
+            split: true,
             format: CtxString::new(DEFAULT_LOG_FORMAT).unwrap(),
+            line_format: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,206 @@
+use std::path::{Path, PathBuf};
+
+use error_stack::{Report, Result, ResultExt};
+
+use crate::{
+    ctx_string::{Context, CtxString},
+    fs::{
+        check_log, check_mount, check_source, check_space, check_target, create_dir, is_remote,
+        parent_dir, CheckError, PathCheck, PathProblem,
+    },
+};
+
+use super::{
+    error::BackupCheckError,
+    keys::ConfigKey,
+    preview::{example_bindings, example_line_bindings},
+    run::{invalid_snapshot_name, REMOTE_SNAPSHOT},
+    Backup, Mode,
+};
+
+pub(super) const NOT_LOGGED: &str =
+    "With `log.split = false` and no `log.combined`, the output isn't logged anywhere";
+
+impl Backup {
+    /// Evaluate every format string of this backup,
+    /// returning an error for each one that can't be evaluated
+    /// and for logging settings that would lose the output
+    pub(crate) fn check(&self, variables: &Context) -> Vec<Report<BackupCheckError>> {
+        let context = self.context(variables);
+        // What comes before `${log}` is written before rsync exits,
+        // so only what comes after it can use the variables describing the run
+        let (header, footer) = self.log_format(&context);
+        let mut log_context = context.clone();
+        log_context.extend(example_bindings());
+        let mut line_context = context.clone();
+        line_context.extend(example_line_bindings());
+
+        let mut strings = vec![
+            (String::from("source"), &self.source, &context),
+            (String::from("target"), &self.target, &context),
+        ];
+        strings.extend(
+            self.exclude
+                .iter()
+                .enumerate()
+                .map(|(i, s)| (format!("exclude[{}]", i), s, &context)),
+        );
+        strings.extend([
+            (String::from("log.stdout"), &self.log.stdout, &context),
+            (String::from("log.stderr"), &self.log.stderr, &context),
+            (String::from("log.format"), &footer, &log_context),
+        ]);
+        if let Some(line_format) = &self.log.line_format {
+            strings.push((String::from("log.line_format"), line_format, &line_context));
+        }
+        if let Some(header) = &header {
+            strings.push((String::from("log.format"), header, &context));
+        }
+        if let Some(changes) = &self.log.changes {
+            strings.push((String::from("log.changes"), changes, &context));
+        }
+        if let Some(combined) = &self.log.combined {
+            strings.push((String::from("log.combined"), combined, &context));
+        }
+
+        if self.method.mode == Mode::Snapshot {
+            strings.push((
+                String::from("method.snapshot_name"),
+                &self.method.snapshot_name,
+                &context,
+            ));
+        }
+
+        let mut problems = Vec::from_iter(strings.into_iter().filter_map(
+            |(key, s, context): (String, &CtxString, &Context)| {
+                s.evaluate(context)
+                    .change_context(BackupCheckError)
+                    .attach_printable(ConfigKey(key))
+                    .err()
+            },
+        ));
+        let remote = self
+            .target
+            .evaluate(&context)
+            .is_ok_and(|target| is_remote(&target));
+        if self.method.mode == Mode::Snapshot && remote {
+            problems.push(
+                Report::new(BackupCheckError)
+                    .attach_printable(ConfigKey(String::from("method.mode")))
+                    .attach_printable(REMOTE_SNAPSHOT),
+            );
+        }
+        let snapshot_name = match self.method.mode {
+            Mode::Snapshot => self.method.snapshot_name.evaluate(&context).ok(),
+            Mode::Mirror => None,
+        };
+        if let Some(problem) = snapshot_name.as_deref().and_then(invalid_snapshot_name) {
+            problems.push(
+                Report::new(BackupCheckError)
+                    .attach_printable(ConfigKey(String::from("method.snapshot_name")))
+                    .attach_printable(problem),
+            );
+        }
+        if !self.logs_output() {
+            problems.push(
+                Report::new(BackupCheckError)
+                    .attach_printable(ConfigKey(String::from("log.split")))
+                    .attach_printable(NOT_LOGGED),
+            );
+        }
+        problems
+    }
+
+    /// Whether rsync's output goes to any log file
+    pub(super) fn logs_output(&self) -> bool {
+        self.log.split || self.log.combined.is_some()
+    }
+
+    /// Check the paths of this backup before running it,
+    /// creating missing directories where allowed.
+    ///
+    /// Reports every failed check, not just the first one.
+    pub(crate) fn preflight(&self, variables: &Context) -> Result<(), CheckError> {
+        let context = self.context(variables);
+        let eval = |key: &str, s: &CtxString| {
+            s.evaluate(&context)
+                .change_context(CheckError)
+                .attach_printable_lazy(|| ConfigKey(key.to_owned()))
+        };
+        let source = eval("source", &self.source)?;
+        let target = eval("target", &self.target)?;
+        let mut logs = Vec::new();
+        if self.log.split {
+            logs.push(("log.stdout", eval("log.stdout", &self.log.stdout)?));
+            logs.push(("log.stderr", eval("log.stderr", &self.log.stderr)?));
+        }
+        if let Some(combined) = &self.log.combined {
+            logs.push(("log.combined", eval("log.combined", combined)?));
+        }
+        if let Some(changes) = &self.log.changes {
+            logs.push(("log.changes", eval("log.changes", changes)?));
+        }
+
+        // Nothing else is worth checking if the target drive is missing,
+        // and creating directories would put them on the wrong drive
+        check_mount(&target, &self.mount).attach_printable(ConfigKey(String::from("target")))?;
+
+        let sudo = self.method.sudo;
+        let target_check = check_target(&target, self.create_target, sudo)
+            .attach_printable(ConfigKey(String::from("target")))
+            .map_err(|e| {
+                match !self.create_target && e.downcast_ref() == Some(&PathProblem::Missing) {
+                    true => e.attach_printable(
+                        "Set `create_target = true` to create missing directories",
+                    ),
+                    false => e,
+                }
+            });
+        let mut checks = vec![
+            (
+                check_source(&source, sudo).attach_printable(ConfigKey(String::from("source"))),
+                None,
+            ),
+            // Creating the target would be a change, which a dry run shouldn't make
+            (
+                target_check,
+                Some(PathBuf::from(&target)).filter(|_| !self.method.dry_run),
+            ),
+        ];
+        for (key, log) in &logs {
+            checks.push((
+                check_log(log).attach_printable(ConfigKey(String::from(*key))),
+                Some(parent_dir(Path::new(log)).to_owned()),
+            ));
+        }
+
+        let mut to_create = Vec::new();
+        let mut failed: Option<Report<CheckError>> = None;
+        for (check, dir) in checks {
+            match check {
+                Ok(PathCheck::Create) => to_create.extend(dir),
+                Ok(_) => (),
+                Err(e) => match failed.as_mut() {
+                    Some(failed) => failed.extend_one(e),
+                    None => failed = Some(e),
+                },
+            }
+        }
+        if let Some(failed) = failed {
+            return Err(failed);
+        }
+
+        to_create.iter().try_for_each(|dir| create_dir(dir))?;
+
+        // A dry run doesn't take up any space
+        if !self.method.dry_run {
+            let transfer = match self.space.estimate {
+                true => Some(self.estimate_transfer(&context)?),
+                false => None,
+            };
+            check_space(&target, &self.space, transfer)
+                .attach_printable(ConfigKey(String::from("target")))?;
+        }
+        Ok(())
+    }
+}
@@ -68,6 +68,28 @@ impl fmt::Display for OutLvlParseError {
     }
 }
 
+#[derive(Debug)]
+pub(crate) struct SizeParseError;
+
+impl Error for SizeParseError {}
+
+impl fmt::Display for SizeParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to parse Size")
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct MethodBuildError;
+
+impl Error for MethodBuildError {}
+
+impl fmt::Display for MethodBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to build Method Config")
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct LogBuildError;
 
@@ -78,3 +100,58 @@ impl fmt::Display for LogBuildError {
         write!(f, "Failed to build Log Config")
     }
 }
+
+#[derive(Debug)]
+pub(crate) struct SelectError;
+
+impl Error for SelectError {}
+
+impl fmt::Display for SelectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to select Backups")
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct BackupCheckError;
+
+impl Error for BackupCheckError {}
+
+impl fmt::Display for BackupCheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to check Backup")
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct PruneError;
+
+impl Error for PruneError {}
+
+impl fmt::Display for PruneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to prune Snapshots")
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct RestoreError;
+
+impl Error for RestoreError {}
+
+impl fmt::Display for RestoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to prepare Restore")
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct VerifyError;
+
+impl Error for VerifyError {}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to verify Backup")
+    }
+}
@@ -0,0 +1,294 @@
+use std::{collections::HashMap, fmt, ops::Range};
+
+use error_stack::Report;
+use serde::{
+    de::{self, Visitor},
+    forward_to_deserialize_any, Deserialize, Deserializer,
+};
+use toml_edit::{ImDocument, Item, Table, Value};
+
+use super::{
+    settings::{PreviewSettingsConfig, RunSettingsConfig, SettingsConfig},
+    BackupConfig, Config, LogConfig, MethodConfig, RetentionConfig, Template,
+};
+
+/// A key in the config file that doesn't mean anything to backer
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct UnknownKey {
+    pub path: String,
+    pub location: Option<(usize, usize)>,
+    pub suggestion: Option<&'static str>,
+}
+
+impl fmt::Display for UnknownKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown key `{}`", self.path)?;
+        if let Some((line, column)) = self.location {
+            write!(f, " at line {}, column {}", line, column)?;
+        }
+        if let Some(suggestion) = self.suggestion {
+            write!(f, ", did you mean `{}`?", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+impl UnknownKey {
+    pub(super) fn new(path: String, spans: &KeySpans) -> Self {
+        let (parent, key) = match path.rsplit_once('.') {
+            Some((parent, key)) => (parent, key),
+            None => ("", path.as_str()),
+        };
+        let suggestion = closest(key, valid_keys(parent));
+        UnknownKey {
+            location: spans.location(&path),
+            suggestion,
+            path,
+        }
+    }
+}
+
+/// Path of a key as reported by `serde_ignored`, like `run[1].method.dryrun`
+pub(super) fn key_path(path: &serde_ignored::Path) -> String {
+    use serde_ignored::Path;
+    match path {
+        Path::Root => String::new(),
+        Path::Seq { parent, index } => format!("{}[{}]", key_path(parent), index),
+        Path::Map { parent, key } => match key_path(parent) {
+            parent if parent.is_empty() => key.to_owned(),
+            parent => format!("{}.{}", parent, key),
+        },
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => key_path(parent),
+    }
+}
+
+/// Attached to errors about a value, naming the key it was set by,
+/// relative to the table of the backup, like `log.stdout`
+#[derive(Clone, Debug)]
+pub(crate) struct ConfigKey(pub String);
+
+impl fmt::Display for ConfigKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Found in `{}`", self.0)
+    }
+}
+
+/// Positions of every key in a config file, by their path like `run[1].log.stdout`
+#[derive(Clone, Debug, Default)]
+pub(crate) struct KeySpans {
+    source: String,
+    spans: HashMap<String, Range<usize>>,
+}
+
+impl KeySpans {
+    pub fn new(source: &str) -> Self {
+        let mut spans = HashMap::new();
+        if let Ok(document) = ImDocument::parse(source) {
+            collect_table(document.as_table(), "", &mut spans);
+        }
+        KeySpans {
+            source: source.to_owned(),
+            spans,
+        }
+    }
+
+    /// Line and column of a key, both starting at 1
+    pub fn location(&self, path: &str) -> Option<(usize, usize)> {
+        let span = self.spans.get(path)?;
+        Some(line_column(&self.source, span.start))
+    }
+
+    /// Point to where the key named by a [`ConfigKey`] attachment was set,
+    /// looking for it in each of `tables` in order.
+    pub fn locate<C>(&self, report: Report<C>, tables: &[&str]) -> Report<C> {
+        let Some(ConfigKey(key)) = report.downcast_ref::<ConfigKey>().cloned() else {
+            return report;
+        };
+        let paths = Vec::from_iter(tables.iter().map(|table| join(table, &key)));
+        let path = paths
+            .iter()
+            .find(|path| self.spans.contains_key(*path))
+            .or(paths.first())
+            .map_or(key.as_str(), String::as_str);
+        report.attach_printable(self.snippet(path))
+    }
+
+    /// The line a key is set on, with a caret under the key
+    fn snippet(&self, path: &str) -> String {
+        let Some((line, column)) = self.location(path) else {
+            return format!("Set by `{}`", path);
+        };
+        let text = self.source.lines().nth(line - 1).unwrap_or_default();
+        let gutter = line.to_string().len();
+        format!(
+            "Set by `{}` at line {}, column {}:\n{} |\n{} | {}\n{} | {}^",
+            path,
+            line,
+            column,
+            " ".repeat(gutter),
+            line,
+            text,
+            " ".repeat(gutter),
+            " ".repeat(column - 1),
+        )
+    }
+}
+
+/// Line and column of a byte offset, both starting at 1
+pub(crate) fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = match before.rfind('\n') {
+        Some(start) => before[start + 1..].chars().count() + 1,
+        None => before.chars().count() + 1,
+    };
+    (line, column)
+}
+
+fn collect_table(table: &Table, prefix: &str, spans: &mut HashMap<String, Range<usize>>) {
+    for (key, item) in table.iter() {
+        let path = join(prefix, key);
+        if let Some(span) = table.key(key).and_then(|k| k.span()) {
+            spans.insert(path.clone(), span);
+        }
+        match item {
+            Item::Table(t) => collect_table(t, &path, spans),
+            Item::ArrayOfTables(a) => {
+                for (i, t) in a.iter().enumerate() {
+                    collect_table(t, &format!("{}[{}]", path, i), spans)
+                }
+            }
+            Item::Value(v) => collect_value(v, &path, spans),
+            Item::None => (),
+        }
+    }
+}
+
+fn collect_value(value: &Value, path: &str, spans: &mut HashMap<String, Range<usize>>) {
+    match value {
+        Value::InlineTable(t) => {
+            for (key, value) in t.iter() {
+                let path = join(path, key);
+                if let Some(span) = t.key(key).and_then(|k| k.span()) {
+                    spans.insert(path.clone(), span);
+                }
+                collect_value(value, &path, spans);
+            }
+        }
+        Value::Array(a) => {
+            for (i, v) in a.iter().enumerate() {
+                let path = format!("{}[{}]", path, i);
+                // Array elements have no key, so point to the value instead
+                if let Some(span) = v.span() {
+                    spans.insert(path.clone(), span);
+                }
+                collect_value(v, &path, spans)
+            }
+        }
+        _ => (),
+    }
+}
+
+fn join(prefix: &str, key: &str) -> String {
+    match prefix.is_empty() {
+        true => key.to_owned(),
+        false => format!("{}.{}", prefix, key),
+    }
+}
+
+/// Keys that are valid in the table at `path`
+fn valid_keys(path: &str) -> &'static [&'static str] {
+    let path = Vec::from_iter(path.split('.').map(|k| k.split('[').next().unwrap_or(k)));
+    match path.as_slice() {
+        [""] => fields::<Config>(),
+        ["settings"] => fields::<SettingsConfig>(),
+        ["settings", "run"] => fields::<RunSettingsConfig>(),
+        ["settings", "preview"] => fields::<PreviewSettingsConfig>(),
+        ["template"] => fields::<Template>(),
+        ["run"] => fields::<BackupConfig>(),
+        ["template" | "run", "method"] => fields::<MethodConfig>(),
+        ["template" | "run", "log"] => fields::<LogConfig>(),
+        ["template" | "run", "retention"] => fields::<RetentionConfig>(),
+        _ => &[],
+    }
+}
+
+/// The valid key closest to `key`, if any is close enough to be a typo
+fn closest(key: &str, valid: &[&'static str]) -> Option<&'static str> {
+    let max_distance = (key.chars().count() / 3).max(1);
+    valid
+        .iter()
+        .map(|v| (distance(key, v), *v))
+        .filter(|(d, _)| *d <= max_distance)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, v)| v)
+}
+
+/// Levenshtein distance between two strings
+fn distance(a: &str, b: &str) -> usize {
+    let b = Vec::from_iter(b.chars());
+    let mut row = Vec::from_iter(0..=b.len());
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { prev } else { prev + 1 };
+            prev = row[j + 1];
+            row[j + 1] = cost.min(row[j] + 1).min(prev + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Names of the fields a struct deserializes, as declared through serde
+fn fields<'de, T: Deserialize<'de>>() -> &'static [&'static str] {
+    match T::deserialize(FieldProbe) {
+        Err(Probed(fields)) => fields,
+        Ok(_) => &[],
+    }
+}
+
+/// A deserializer that fails immediately, reporting the fields it was asked for
+struct FieldProbe;
+
+#[derive(Debug)]
+struct Probed(&'static [&'static str]);
+
+impl fmt::Display for Probed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "probed fields {:?}", self.0)
+    }
+}
+
+impl std::error::Error for Probed {}
+
+impl de::Error for Probed {
+    fn custom<T: fmt::Display>(_: T) -> Self {
+        Probed(&[])
+    }
+}
+
+impl<'de> Deserializer<'de> for FieldProbe {
+    type Error = Probed;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Probed> {
+        Err(Probed(&[]))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        fields: &'static [&'static str],
+        _: V,
+    ) -> Result<V::Value, Probed> {
+        Err(Probed(fields))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
@@ -1,129 +1,585 @@
-use std::fmt::Display;
+use std::{path::Path, process::Command};
+
+use error_stack::{Report, Result};
 
 use crate::{
     config::OutLvl,
     ctx_string::{Context, CtxString},
+    fs::{
+        check_log, check_mount, check_source, check_space, check_target, format_size, free_space,
+        CheckError, MountProblem, PathCheck, PathProblem, SpaceProblem,
+    },
+    rsync::TransferStats,
+    term::{Color, Style, PAGE_WIDTH},
 };
 
-use super::Backup;
+use super::{
+    check::NOT_LOGGED, Backup, LINE_BINDING, LINE_NO_BINDING, LOG_BINDING, OUTCOME_BINDING,
+    STATS_BINDING, STATUS_BINDING, STATUS_MEANING_BINDING, STREAM_BINDING,
+};
 
-const PAGE_WIDTH: usize = 80;
-const INDENT: usize = 4;
+const INDENT: usize = 2;
 const SPACE: usize = 2;
 
+const SAME_AS_TEMPLATE: &str = "(Same as Template)";
+const REMOTE: &str = "(REMOTE)";
+const LOG_PLACEHOLDER: &str = "[Log goes here]";
+const STATUS_PLACEHOLDER: &str = "[Status]";
+const STATUS_MEANING_PLACEHOLDER: &str = "[Status meaning]";
+const OUTCOME_PLACEHOLDER: &str = "[Outcome]";
+const LINE_PLACEHOLDER: &str = "[Line]";
+const LINE_NO_PLACEHOLDER: &str = "[Line number]";
+const STREAM_PLACEHOLDER: &str = "[Stream]";
+
 impl Backup {
-    pub(crate) fn preview(&self, variables: &Context, name: String) {
-        let name = format!("BACKUP {}", name);
-        let space = " ";
-        let taglen = 2 * space.len() + name.len();
-        let bar_a = "═".repeat(taglen);
-        let bar_b = "═".repeat(PAGE_WIDTH - taglen - 1);
-        println!(
-            "{bar_a}╦{bar_b}\n\
-            {space}{name}{space}║\n\
-            {bar_a}╝",
-        );
+    /// Preview the values every backup falls back to
+    pub(crate) fn preview_template(&self, variables: &Context, style: &Style) {
+        let context = self.context(variables);
+        let mut errors = Vec::new();
+
+        let paths = self.log_paths(&context, &mut errors);
+        let sections = vec![
+            Section::new("GENERAL").group(self.general_points(&context, &mut errors)),
+            self.method_section(),
+            self.logging_section(&context, paths, &mut errors),
+        ];
+
+        print_block(style, "TEMPLATE", sections, errors);
+    }
+
+    /// Preview a backup exactly as `run` would execute it
+    pub(crate) fn preview(
+        &self,
+        variables: &Context,
+        name: &str,
+        style: &Style,
+        template: &Backup,
+    ) {
+        let mut errors = Vec::new();
+        // Evaluation errors are already part of a failed compilation,
+        // so only failed path checks are reported in addition
+        let mut checks = Vec::new();
+
+        let (context, command) = match self.compile(variables) {
+            Ok(compiled) => (
+                compiled.context,
+                PointContent::Multi(wrap(&command_line(&compiled.command))),
+            ),
+            Err(e) => {
+                errors.push(format!("{:?}", e));
+                (self.context(variables), PointContent::Error)
+            }
+        };
+        let sudo = self.method.sudo;
+        let source = eval_path(&self.source, &context, &mut checks, |p| {
+            check_source(p, sudo)
+        });
+        let target = eval_path(&self.target, &context, &mut checks, |p| {
+            check_mount(p, &self.mount)
+                .and_then(|_| check_target(p, self.create_target, sudo))
+                .and_then(|check| check_space(p, &self.space, None).map(|_| check))
+        });
+        let target = with_free_space(target);
+        let paths = self.log_paths(&context, &mut checks);
+        if errors.is_empty() {
+            errors.extend(checks);
+        }
+
+        let mut method = self.method_section();
+        if self.method == template.method {
+            method = method.note(SAME_AS_TEMPLATE);
+        }
+        let mut logging = self.logging_section(&context, paths, &mut Vec::new());
+        if self.log == template.log {
+            logging = logging.note(SAME_AS_TEMPLATE);
+        }
+
+        let mut general = Section::new("GENERAL");
+        if let Some(description) = &self.description {
+            general = general.group(vec![(
+                "Description:",
+                PointContent::Single(description.to_owned()),
+            )]);
+        }
+
+        let sections = vec![
+            Section::new("COMMAND").group(vec![("", command)]),
+            general
+                .group(vec![("Source:", source), ("Target:", target)])
+                .group(self.general_points(&context, &mut Vec::new())),
+            method,
+            logging,
+        ];
+
+        print_block(style, &format!("BACKUP {}", name), sections, errors);
+    }
 
-        let source = PointContent::from(self.source.evaluate(&variables));
-        let target = PointContent::from(self.target.evaluate(&variables));
+    fn general_points(
+        &self,
+        context: &Context,
+        errors: &mut Vec<String>,
+    ) -> Vec<(&'static str, PointContent)> {
         let exclude = if self.exclude.is_empty() {
             PointContent::Single(String::from("[]"))
         } else {
             PointContent::Multi(
                 self.exclude
                     .iter()
-                    .map(|x| match x.evaluate(&variables) {
+                    .map(|x| match x.evaluate(context) {
                         Ok(s) => s,
-                        Err(_) => String::from("ERROR"),
+                        Err(e) => {
+                            errors.push(format!("{:?}", e));
+                            String::from("ERROR")
+                        }
                     })
-                    .collect::<Vec<_>>(),
+                    .collect(),
             )
         };
-        let output = PointContent::Single(
-            match self.output {
-                OutLvl::Quiet => "quiet",
-                OutLvl::Default => "default",
-                OutLvl::Verbose => "verbose",
-            }
-            .to_string(),
+        let output = match self.output {
+            OutLvl::Quiet => "quiet",
+            OutLvl::Default => "default",
+            OutLvl::Verbose => "verbose",
+            OutLvl::Progress => "progress",
+        };
+
+        vec![
+            ("Create target:", PointContent::from(self.create_target)),
+            (
+                "Require mount:",
+                PointContent::Single(self.mount_requirement()),
+            ),
+            ("Min free:", PointContent::Single(self.space_requirement())),
+            ("Exclude:", exclude),
+            ("Output:", PointContent::Single(output.to_owned())),
+            (
+                "On failure:",
+                PointContent::Single(self.on_failure.to_string()),
+            ),
+        ]
+    }
+
+    fn mount_requirement(&self) -> String {
+        let mut drive = Vec::new();
+        if let Some(uuid) = &self.mount.uuid {
+            drive.push(format!("UUID {:?}", uuid));
+        }
+        if let Some(label) = &self.mount.label {
+            drive.push(format!("label {:?}", label));
+        }
+        match (self.mount.mounted, drive.is_empty()) {
+            (mounted, true) => mounted.to_string(),
+            (_, false) => format!("drive with {}", drive.join(" and ")),
+        }
+    }
+
+    fn space_requirement(&self) -> String {
+        let mut required = Vec::new();
+        if let Some(min_free) = self.space.min_free {
+            required.push(format_size(min_free));
+        }
+        if let Some(percent) = self.space.min_free_percent {
+            required.push(format!("{}%", percent));
+        }
+        if self.space.estimate {
+            required.push(String::from("after estimated transfer"));
+        }
+        match required.is_empty() {
+            true => String::from("none"),
+            false => required.join(", "),
+        }
+    }
+
+    fn retention_policy(&self) -> String {
+        let rules = [
+            ("last", self.retention.keep_last),
+            ("daily", self.retention.keep_daily),
+            ("weekly", self.retention.keep_weekly),
+            ("monthly", self.retention.keep_monthly),
+            ("yearly", self.retention.keep_yearly),
+        ];
+        let rules = Vec::from_iter(
+            rules
+                .iter()
+                .filter_map(|(rule, keep)| keep.map(|n| format!("{} {}", rule, n))),
         );
+        match rules.is_empty() {
+            true => String::from("keep all"),
+            false => rules.join(", "),
+        }
+    }
+
+    fn method_section(&self) -> Section {
+        let codes = |codes: &[i32]| {
+            let codes = Vec::from_iter(codes.iter().map(i32::to_string));
+            PointContent::Single(format!("[{}]", codes.join(", ")))
+        };
+
+        Section::new("METHOD")
+            .group(vec![
+                ("Mode:", PointContent::Single(self.method.mode.to_string())),
+                ("Retention:", PointContent::Single(self.retention_policy())),
+                ("Stats:", PointContent::from(self.method.stats)),
+                ("Itemize:", PointContent::from(self.method.itemize)),
+                ("Sudo:", PointContent::from(self.method.sudo)),
+                ("Delete:", PointContent::from(self.method.delete)),
+                ("Dry run:", PointContent::from(self.method.dry_run)),
+            ])
+            .group(vec![
+                ("Accept codes:", codes(&self.method.accept_codes)),
+                ("Warn codes:", codes(&self.method.warn_codes)),
+            ])
+    }
 
-        let general = section(
-            "GENERAL",
-            &[
-                ("Source:", source),
-                ("Target:", target),
-                ("Exclude:", exclude),
-                ("Output:", output),
-            ],
+    /// Every file this backup logs to, checked like before a run
+    fn log_paths(
+        &self,
+        context: &Context,
+        errors: &mut Vec<String>,
+    ) -> Vec<(&'static str, PointContent)> {
+        let mut paths = Vec::new();
+        if self.log.split {
+            paths.push(("Output:", &self.log.stdout));
+            paths.push(("Errors:", &self.log.stderr));
+        }
+        paths.extend(self.log.combined.as_ref().map(|p| ("Combined:", p)));
+        paths.extend(self.log.changes.as_ref().map(|p| ("Changes:", p)));
+        let mut paths = Vec::from_iter(
+            paths
+                .into_iter()
+                .map(|(label, path)| (label, eval_path(path, context, errors, check_log))),
         );
+        if !self.logs_output() {
+            errors.push(String::from(NOT_LOGGED));
+            paths.insert(
+                0,
+                (
+                    "Output:",
+                    PointContent::Single(String::from("(NOT LOGGED)")),
+                ),
+            );
+        }
+        paths
+    }
+
+    fn logging_section(
+        &self,
+        context: &Context,
+        paths: Vec<(&'static str, PointContent)>,
+        errors: &mut Vec<String>,
+    ) -> Section {
+        let mut context = context.clone();
+        context.extend(example_bindings());
+
+        // Show a formatted line in place of the output
+        if let Some(line_format) = &self.log.line_format {
+            let mut line_context = context.clone();
+            line_context.extend(example_line_bindings());
+            match line_format.evaluate(&line_context) {
+                Ok(line) => {
+                    context.insert(LOG_BINDING.to_owned(), CtxString::literal(&line));
+                }
+                Err(e) => errors.push(format!("{:?}", e)),
+            }
+        }
+
+        let example = match self.log.format.evaluate(&context) {
+            Ok(log) => PointContent::Log(log),
+            Err(e) => {
+                errors.push(format!("{:?}", e));
+                PointContent::Error
+            }
+        };
 
-        println!("{}", general);
+        Section::new("LOGGING")
+            .group(paths)
+            .group(vec![
+                ("Append:", PointContent::from(self.log.append)),
+                ("Example log:", PointContent::Single(String::new())),
+            ])
+            .group(vec![("", example)])
     }
 }
 
+/// Placeholders for the variables only available to `log.format`
+pub(super) fn example_bindings() -> Vec<(String, CtxString)> {
+    let bindings = [
+        (LOG_BINDING, LOG_PLACEHOLDER),
+        (STATUS_BINDING, STATUS_PLACEHOLDER),
+        (STATUS_MEANING_BINDING, STATUS_MEANING_PLACEHOLDER),
+        (OUTCOME_BINDING, OUTCOME_PLACEHOLDER),
+    ]
+    .map(|(k, v)| (k.to_owned(), CtxString::literal(v)));
+    let stats = TransferStats::default().fields().map(|(name, _)| {
+        let name = format!("{}.{}", STATS_BINDING, name);
+        let placeholder = CtxString::literal(&format!("[{}]", name));
+        (name, placeholder)
+    });
+    Vec::from_iter(bindings.into_iter().chain(stats))
+}
+
+/// Placeholders for the variables only available to `log.line_format`
+pub(super) fn example_line_bindings() -> [(String, CtxString); 3] {
+    [
+        (LINE_BINDING, LINE_PLACEHOLDER),
+        (LINE_NO_BINDING, LINE_NO_PLACEHOLDER),
+        (STREAM_BINDING, STREAM_PLACEHOLDER),
+    ]
+    .map(|(k, v)| (k.to_owned(), CtxString::literal(v)))
+}
+
 enum PointContent {
     Single(String),
     Multi(Vec<String>),
+    /// A path, annotated with the result of checking it
+    Path(String, String, Color),
+    /// Multiple lines, prefixed with line numbers
+    Log(String),
+    Error,
 }
-impl From<String> for PointContent {
-    fn from(value: String) -> Self {
-        PointContent::Single(value)
+
+impl From<bool> for PointContent {
+    fn from(value: bool) -> Self {
+        PointContent::Single(value.to_string())
     }
 }
 
-impl<T, E> From<Result<T, E>> for PointContent
-where
-    PointContent: From<T>,
-{
-    fn from(value: Result<T, E>) -> Self {
-        match value {
-            Ok(t) => PointContent::from(t),
-            Err(_) => PointContent::Single(String::from("ERROR")),
+struct Section {
+    title: &'static str,
+    note: Option<&'static str>,
+    groups: Vec<Vec<(&'static str, PointContent)>>,
+}
+
+impl Section {
+    fn new(title: &'static str) -> Self {
+        Section {
+            title,
+            note: None,
+            groups: Vec::new(),
         }
     }
-}
 
-impl<T> From<&[T]> for PointContent
-where
-    PointContent: From<T>,
-{
-    fn from(value: &[T]) -> Self {
-        PointContent::Multi()
+    /// Add a note to the right of the title
+    fn note(mut self, note: &'static str) -> Self {
+        self.note = Some(note);
+        self
     }
-}
 
-fn section(title: &str, points: &[(&str, PointContent)]) -> String {
-    let max_tag_len = points
-        .into_iter()
-        .map(|(a, _)| a.to_string().len())
-        .reduce(usize::max)
-        .unwrap_or_default();
+    /// Add a group of points, separated from other groups by an empty line.
+    /// Empty groups are left out.
+    fn group(mut self, points: Vec<(&'static str, PointContent)>) -> Self {
+        if !points.is_empty() {
+            self.groups.push(points);
+        }
+        self
+    }
 
-    let points = points
-        .into_iter()
-        .map(|(t, c)| point(t, c, max_tag_len))
-        .collect::<Vec<_>>()
-        .join("\n");
+    fn render(&self, style: &Style) -> String {
+        let points = self.groups.iter().flatten();
+        let max_tag_len = points
+            .clone()
+            .map(|(tag, _)| tag.len())
+            .max()
+            .unwrap_or_default();
+        let max_path_len = points
+            .filter_map(|(_, con)| match con {
+                PointContent::Path(p, _, _) => Some(p.chars().count()),
+                _ => None,
+            })
+            .max()
+            .unwrap_or_default();
 
-    format!("{}\n\n{}", title, points)
+        let title = match self.note {
+            Some(note) => format!(
+                "{}{}{}",
+                style.paint(self.title, Color::Bold),
+                " ".repeat(PAGE_WIDTH.saturating_sub(self.title.len() + note.len())),
+                style.paint(note, Color::Dim)
+            ),
+            None => style.paint(self.title, Color::Bold),
+        };
+
+        let groups = self
+            .groups
+            .iter()
+            .map(|group| {
+                group
+                    .iter()
+                    .map(|(tag, con)| point(style, tag, con, max_tag_len, max_path_len))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        format!("{}\n\n{}", title, groups)
+    }
 }
 
-fn point(tag: impl Display, con: &PointContent, max_tag_len: usize) -> String {
-    let tag = tag.to_string();
+fn print_block(style: &Style, title: &str, sections: Vec<Section>, errors: Vec<String>) {
+    let mut sections = Vec::from_iter(sections.iter().map(|s| s.render(style)));
+    if !errors.is_empty() {
+        let errors = errors
+            .iter()
+            .flat_map(|e| e.lines())
+            .map(|l| format!("{}{}", " ".repeat(INDENT), l))
+            .collect::<Vec<_>>()
+            .join("\n");
+        sections.push(format!(
+            "{}\n\n{}",
+            style.paint("ERRORS", Color::Bold),
+            errors
+        ));
+    }
+
+    println!("{}", style.header(title));
+    println!("{}", sections.join(&format!("\n{}\n", style.rule())));
+}
+
+fn point(
+    style: &Style,
+    tag: &str,
+    con: &PointContent,
+    max_tag_len: usize,
+    max_path_len: usize,
+) -> String {
     let indent = " ".repeat(INDENT);
-    let space = " ".repeat(SPACE + max_tag_len - tag.len());
+    let space = match max_tag_len {
+        0 => String::new(),
+        _ => " ".repeat(SPACE + max_tag_len - tag.len()),
+    };
 
     match con {
-        PointContent::Single(con) => format!("{}{}{}{}", indent, tag, space, con),
+        PointContent::Single(con) => format!("{}{}{}{}", indent, tag, space, con)
+            .trim_end()
+            .to_owned(),
+        PointContent::Error => {
+            let error = style.paint("ERROR", Color::Red);
+            format!("{}{}{}{}", indent, tag, space, error)
+        }
+        PointContent::Path(path, note, color) => {
+            let fill = " ".repeat(max_path_len - path.chars().count() + 1);
+            let check = style.paint(note, *color);
+            format!("{}{}{}{}{}{}", indent, tag, space, path, fill, check)
+        }
         PointContent::Multi(s) => s
-            .into_iter()
+            .iter()
             .enumerate()
             .map(|(i, con)| {
-                let tag = if i == 0 { tag.as_str() } else { "" };
-                point(tag, &PointContent::Single(con.to_owned()), max_tag_len)
+                let tag = if i == 0 { tag } else { "" };
+                point(
+                    style,
+                    tag,
+                    &PointContent::Single(con.to_owned()),
+                    max_tag_len,
+                    0,
+                )
             })
             .collect::<Vec<_>>()
             .join("\n"),
+        PointContent::Log(log) => {
+            let lines = Vec::from_iter(log.split('\n'));
+            let width = (lines.len() - 1).to_string().len();
+            lines
+                .iter()
+                .enumerate()
+                .map(|(i, line)| {
+                    let num = style.paint(format!("{:>width$}│", i), Color::Dim);
+                    format!("{}{} {}", indent, num, line)
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+    }
+}
+
+fn eval_path(
+    s: &CtxString,
+    context: &Context,
+    errors: &mut Vec<String>,
+    check: impl FnOnce(&str) -> Result<PathCheck, CheckError>,
+) -> PointContent {
+    match s.evaluate(context) {
+        Ok(path) => {
+            let (note, color) = match check(&path) {
+                Ok(PathCheck::Ok) => ("(OK)", Color::Green),
+                Ok(PathCheck::Create) => ("(WILL BE CREATED)", Color::Yellow),
+                Ok(PathCheck::Remote) => (REMOTE, Color::Dim),
+                Err(e) => {
+                    let note = problem_note(&e);
+                    errors.push(format!("{:?}", e));
+                    (note, Color::Red)
+                }
+            };
+            PointContent::Path(path, note.to_owned(), color)
+        }
+        Err(e) => {
+            errors.push(format!("{:?}", e));
+            PointContent::Error
+        }
+    }
+}
+
+/// Add the free space of the filesystem to a checked path, like `(OK, 20.0 GiB free)`
+fn with_free_space(point: PointContent) -> PointContent {
+    match point {
+        PointContent::Path(path, note, color) if note != REMOTE => {
+            let note = match free_space(Path::new(&path)) {
+                Ok(space) => format!(
+                    "{}, {} free)",
+                    note.trim_end_matches(')'),
+                    format_size(space.free)
+                ),
+                Err(_) => note,
+            };
+            PointContent::Path(path, note, color)
+        }
+        point => point,
+    }
+}
+
+fn problem_note(report: &Report<CheckError>) -> &'static str {
+    if report.contains::<MountProblem>() {
+        return "(NOT MOUNTED)";
+    }
+    if report.contains::<SpaceProblem>() {
+        return "(LOW ON SPACE)";
+    }
+    match report.downcast_ref::<PathProblem>() {
+        Some(PathProblem::Unreadable) => "(NOT READABLE)",
+        Some(PathProblem::Unwritable) => "(NOT WRITABLE)",
+        Some(PathProblem::Missing) | None => "(NOT FOUND)",
+    }
+}
+
+/// The command as it would be typed into a shell
+fn command_line(command: &Command) -> String {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| {
+            let arg = arg.to_string_lossy();
+            match arg.contains(|c: char| c.is_whitespace() || "'\"\\$`".contains(c)) {
+                true => format!("'{}'", arg.replace('\'', r"'\''")),
+                false => arg.into_owned(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Wrap words into lines fitting the page
+fn wrap(text: &str) -> Vec<String> {
+    let width = PAGE_WIDTH - INDENT;
+    let mut lines = vec![String::new()];
+    for word in text.split(' ') {
+        let line = lines.last_mut().unwrap();
+        if line.is_empty() {
+            line.push_str(word);
+        } else if line.len() + word.len() < width {
+            line.push(' ');
+            line.push_str(word);
+        } else {
+            lines.push(word.to_owned());
+        }
     }
+    lines
 }
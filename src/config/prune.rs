@@ -0,0 +1,158 @@
+use std::{
+    fs::{canonicalize, read_dir, remove_dir_all},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use chrono::{Datelike, NaiveDateTime};
+use error_stack::{Report, Result, ResultExt};
+
+use crate::ctx_string::Context;
+
+use super::{error::PruneError, Backup, Mode, Retention, LATEST_SNAPSHOT};
+
+/// The period a snapshot was taken in, like its day or month
+type Period = fn(&NaiveDateTime) -> (i32, u32);
+
+/// A snapshot directory found in the target of a backup
+#[derive(Debug)]
+pub(crate) struct SnapshotDir {
+    pub path: PathBuf,
+    /// When the snapshot was taken, according to its name
+    pub date: NaiveDateTime,
+    /// Whether the retention policy keeps this snapshot
+    pub keep: bool,
+    /// Whether `latest` points to this snapshot, which is always kept
+    pub latest: bool,
+}
+
+impl Backup {
+    /// Only snapshot backups with a retention policy are pruned
+    pub(crate) fn prunes(&self) -> bool {
+        self.method.mode == Mode::Snapshot && self.retention.is_set()
+    }
+
+    /// All snapshots in the target, newest first, and whether to keep them.
+    ///
+    /// Anything that isn't a directory named like `method.snapshot_name` is ignored.
+    pub(crate) fn snapshots(&self, variables: &Context) -> Result<Vec<SnapshotDir>, PruneError> {
+        let context = self.context(variables);
+        let target = self.target.evaluate(&context).change_context(PruneError)?;
+        let target = Path::new(&target);
+        let latest = canonicalize(target.join(LATEST_SNAPSHOT)).ok();
+
+        let mut snapshots = Vec::new();
+        let entries = read_dir(target)
+            .change_context(PruneError)
+            .attach_printable_lazy(|| format!("Failed to read target {:?}", target))?;
+        for entry in entries {
+            let entry = entry.change_context(PruneError)?;
+            let Some(name) = entry.file_name().to_str().map(str::to_owned) else {
+                continue;
+            };
+            // Symlinks don't count as directories here
+            if name == LATEST_SNAPSHOT || !entry.file_type().is_ok_and(|t| t.is_dir()) {
+                continue;
+            }
+            let date = self
+                .method
+                .snapshot_name
+                .parse_date(&context, &name)
+                .change_context(PruneError)?;
+            if let Some(date) = date {
+                let path = entry.path();
+                snapshots.push(SnapshotDir {
+                    latest: latest.is_some() && canonicalize(&path).ok() == latest,
+                    path,
+                    date,
+                    keep: false,
+                });
+            }
+        }
+
+        snapshots.sort_by(|a, b| b.date.cmp(&a.date).then_with(|| b.path.cmp(&a.path)));
+        let dates = Vec::from_iter(snapshots.iter().map(|s| s.date));
+        for (snapshot, keep) in snapshots.iter_mut().zip(self.retention.keep(&dates)) {
+            snapshot.keep = keep || snapshot.latest;
+        }
+        Ok(snapshots)
+    }
+
+    /// Delete a snapshot, with `sudo` if the backup runs with it
+    pub(crate) fn remove_snapshot(&self, snapshot: &SnapshotDir) -> Result<(), PruneError> {
+        if snapshot.keep {
+            return Err(Report::new(PruneError).attach_printable(format!(
+                "Refusing to delete kept snapshot {:?}",
+                snapshot.path
+            )));
+        }
+        if !self.method.sudo {
+            return remove_dir_all(&snapshot.path)
+                .change_context(PruneError)
+                .attach_printable_lazy(|| format!("Failed to delete {:?}", snapshot.path));
+        }
+
+        let status = Command::new("sudo")
+            .args(["rm", "-rf", "--"])
+            .arg(&snapshot.path)
+            .status()
+            .change_context(PruneError)
+            .attach_printable("Failed to run sudo")?;
+        match status.success() {
+            true => Ok(()),
+            false => Err(Report::new(PruneError)
+                .attach_printable(format!("Failed to delete {:?}", snapshot.path))
+                .attach_printable(format!("rm exited with {}", status))),
+        }
+    }
+}
+
+impl Retention {
+    pub(super) fn is_set(&self) -> bool {
+        [
+            self.keep_last,
+            self.keep_daily,
+            self.keep_weekly,
+            self.keep_monthly,
+            self.keep_yearly,
+        ]
+        .iter()
+        .any(Option::is_some)
+    }
+
+    /// Which of the snapshots taken at `dates`, sorted newest first, to keep.
+    ///
+    /// `keep_last` keeps the newest snapshots, the other rules
+    /// keep the newest snapshot of as many days, weeks, ... as they allow.
+    pub(super) fn keep(&self, dates: &[NaiveDateTime]) -> Vec<bool> {
+        let mut keep = vec![false; dates.len()];
+        for k in keep.iter_mut().take(self.keep_last.unwrap_or_default()) {
+            *k = true;
+        }
+
+        let periods: [(Option<usize>, Period); 4] = [
+            (self.keep_daily, |d| (d.year(), d.ordinal())),
+            (self.keep_weekly, |d| {
+                (d.iso_week().year(), d.iso_week().week())
+            }),
+            (self.keep_monthly, |d| (d.year(), d.month())),
+            (self.keep_yearly, |d| (d.year(), 0)),
+        ];
+        for (count, period) in periods {
+            let count = count.unwrap_or_default();
+            let mut last = None;
+            let mut kept = 0;
+            for (i, date) in dates.iter().enumerate() {
+                if kept >= count {
+                    break;
+                }
+                if last != Some(period(date)) {
+                    last = Some(period(date));
+                    keep[i] = true;
+                    kept += 1;
+                }
+            }
+        }
+        keep
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    path::{Component, Path, PathBuf},
+    process::Command,
+};
+
+use error_stack::{Report, Result, ResultExt};
+
+use crate::{ctx_string::Context, fs::is_remote};
+
+use super::{
+    error::RestoreError,
+    run::{
+        ARG_DELETE, ARG_DRY_RUN, ARG_EXCLUDE, ARG_VERBOSE, BASE_ARGS, COMMAND_RSYNC, COMMAND_SUDO,
+    },
+    Backup, Mode, LATEST_SNAPSHOT,
+};
+
+/// What to restore from a backup, and where to
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Restore {
+    /// Name or date prefix of the snapshot to restore, `latest` if not set
+    pub snapshot: Option<String>,
+    /// Where to restore to, instead of the backup's source
+    pub to: Option<PathBuf>,
+    /// Only restore this path, relative to the backup's source
+    pub path: Option<PathBuf>,
+    /// Delete files in the destination that aren't in the backup
+    pub delete: bool,
+}
+
+/// An rsync invocation copying a backup back
+pub(crate) struct RestoreCommand {
+    pub from: String,
+    pub to: String,
+    args: Vec<String>,
+}
+
+impl RestoreCommand {
+    pub(crate) fn command(&self, dry_run: bool) -> Command {
+        let mut command = Command::new(&self.args[0]);
+        command.args(&self.args[1..]);
+        if dry_run {
+            command.args([ARG_DRY_RUN, ARG_VERBOSE]);
+        }
+        command.args([&self.from, &self.to]);
+        command
+    }
+}
+
+impl Backup {
+    /// Build the rsync invocation restoring this backup.
+    ///
+    /// `method.delete` is ignored, files are only deleted if `restore.delete` is set.
+    pub(crate) fn restore(
+        &self,
+        variables: &Context,
+        restore: &Restore,
+    ) -> Result<RestoreCommand, RestoreError> {
+        let context = self.context(variables);
+        let source = self
+            .source
+            .evaluate(&context)
+            .change_context(RestoreError)?;
+
+        // Without a trailing `/`, rsync copied the source itself into the target
+        let mut from = self.restore_root(variables, restore.snapshot.as_deref())?;
+        if !source.ends_with('/') {
+            if let Some(name) = Path::new(&source).file_name() {
+                from.push(name);
+            }
+        }
+        let mut to = match &restore.to {
+            Some(to) => to.clone(),
+            None => PathBuf::from(&source),
+        };
+        if let Some(path) = &restore.path {
+            if !path.components().all(|c| matches!(c, Component::Normal(_))) {
+                return Err(Report::new(RestoreError).attach_printable(format!(
+                    "{:?} has to be a relative path inside the backup, without `..`",
+                    path
+                )));
+            }
+            from.push(path);
+            to.push(path);
+        }
+
+        // Directories are copied by their contents, so their names don't have to match
+        let from = from.to_string_lossy().trim_end_matches('/').to_owned();
+        let to = to.to_string_lossy().trim_end_matches('/').to_owned();
+        let (from, to) = match is_remote(&from) || Path::new(&from).is_dir() {
+            true => (format!("{}/", from), format!("{}/", to)),
+            false => (from, to),
+        };
+
+        let mut args = Vec::new();
+        if self.method.sudo {
+            args.push(COMMAND_SUDO.to_owned());
+        }
+        args.extend([COMMAND_RSYNC.to_owned(), BASE_ARGS.to_owned()]);
+        if restore.delete {
+            args.push(ARG_DELETE.to_owned());
+        }
+        for exclude in &self.exclude {
+            let exclude = exclude.evaluate(&context).change_context(RestoreError)?;
+            args.push(format!("{}={}", ARG_EXCLUDE, exclude));
+        }
+        Ok(RestoreCommand { from, to, args })
+    }
+
+    /// The directory in the target holding the backup to restore
+    fn restore_root(
+        &self,
+        variables: &Context,
+        snapshot: Option<&str>,
+    ) -> Result<PathBuf, RestoreError> {
+        let target = self
+            .target
+            .evaluate(&self.context(variables))
+            .change_context(RestoreError)?;
+        let target = PathBuf::from(target);
+
+        let snapshot = match (self.method.mode, snapshot) {
+            (Mode::Mirror, None) => return Ok(target),
+            (Mode::Mirror, Some(_)) => {
+                return Err(Report::new(RestoreError)
+                    .attach_printable("Only backups in snapshot mode have snapshots"))
+            }
+            (Mode::Snapshot, None) => return Ok(target.join(LATEST_SNAPSHOT)),
+            (Mode::Snapshot, Some(snapshot)) => snapshot,
+        };
+        if snapshot.contains('/') {
+            return Err(Report::new(RestoreError)
+                .attach_printable(format!("{:?} is not a snapshot name", snapshot)));
+        }
+        if target.join(snapshot).is_dir() {
+            return Ok(target.join(snapshot));
+        }
+
+        // Otherwise pick the newest snapshot starting with it, like a date
+        let snapshots = self.snapshots(variables).change_context(RestoreError)?;
+        snapshots
+            .into_iter()
+            .find(|s| {
+                s.path
+                    .file_name()
+                    .is_some_and(|n| n.to_string_lossy().starts_with(snapshot))
+            })
+            .map(|s| s.path)
+            .ok_or_else(|| {
+                Report::new(RestoreError).attach_printable(format!(
+                    "No snapshot matching {:?} in {:?}",
+                    snapshot, target
+                ))
+            })
+    }
+}
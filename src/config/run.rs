@@ -1,58 +1,156 @@
-use std::process::{Command, ExitStatus};
+use std::{
+    fs::canonicalize,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus},
+};
 
-use error_stack::{Result, ResultExt};
+use error_stack::{Report, Result, ResultExt};
 
 use crate::{
     ctx_string::{Context, CtxString},
-    fs::SaveLogError,
-    runner::run_command,
+    fs::{is_remote, replace_symlink, CheckError},
+    rsync::{
+        describe_status, Outcome, TransferStats, ARG_ITEMIZE_CHANGES, ARG_NO_INC_RECURSIVE,
+        ARG_PROGRESS, ARG_STATS,
+    },
+    runner::{run_command, CommandOutput, RunOptions, Stream},
 };
 
 use super::{
     error::{BackupCompileError, BackupRunError},
-    Backup, OutLvl, LOG_BINDING, SOURCE_BINDING, TARGET_BINDING,
+    keys::ConfigKey,
+    Backup, Mode, OutLvl, LATEST_SNAPSHOT, LINE_BINDING, LINE_NO_BINDING, LOG_BINDING,
+    NAME_BINDING, OUTCOME_BINDING, SNAPSHOT_BINDING, SOURCE_BINDING, STATS_BINDING, STATUS_BINDING,
+    STATUS_MEANING_BINDING, STREAM_BINDING, TARGET_BINDING,
 };
 
-const COMMAND_SUDO: &str = "sudo";
-const COMMAND_RSYNC: &str = "rsync";
-const BASE_ARGS: &str = "-aAx";
-const ARG_DELETE: &str = "--delete";
-const ARG_DRY_RUN: &str = "--dry-run";
+pub(super) const COMMAND_SUDO: &str = "sudo";
+pub(super) const COMMAND_RSYNC: &str = "rsync";
+pub(super) const BASE_ARGS: &str = "-aAx";
+pub(super) const ARG_DELETE: &str = "--delete";
+pub(super) const ARG_DRY_RUN: &str = "--dry-run";
 const ARG_QUIET: &str = "--quiet";
-const ARG_VERBOSE: &str = "--verbose";
-const ARG_EXCLUDE: &str = "--exclude";
+pub(super) const ARG_VERBOSE: &str = "--verbose";
+pub(super) const ARG_EXCLUDE: &str = "--exclude";
+pub(super) const ARG_CHECKSUM: &str = "--checksum";
+const ARG_LINK_DEST: &str = "--link-dest";
+
+/// Finding the latest snapshot and hard-linking to it
+/// only works on the local filesystem
+pub(super) const REMOTE_SNAPSHOT: &str =
+    "Snapshot mode needs a local target, remote targets can only be mirrored";
+
+/// A backup with all its format strings evaluated
+pub(super) struct Compiled {
+    pub context: Context,
+    pub command: Command,
+    pub stdout: String,
+    pub stderr: String,
+    /// Where to list every changed file, if anywhere
+    pub changes: Option<String>,
+    /// Where to log stdout and stderr together, if anywhere
+    pub combined: Option<String>,
+    /// The part of `log.format` before `${log}`, written when rsync starts,
+    /// or `None` if the output of rsync isn't logged
+    pub log_header: Option<String>,
+    /// The part of `log.format` after `${log}`, evaluated when rsync exits
+    pub log_footer: CtxString,
+    pub snapshot: Option<Snapshot>,
+}
+
+/// Where a run in snapshot mode goes
+pub(super) struct Snapshot {
+    /// The directory of this run, inside the target
+    pub dir: PathBuf,
+    /// Symlink to the latest successful snapshot
+    pub latest: PathBuf,
+}
+
+impl Snapshot {
+    /// The latest successful snapshot to hard-link unchanged files from, if any
+    fn link_dest(&self) -> Option<PathBuf> {
+        canonicalize(&self.latest).ok().filter(|p| *p != self.dir)
+    }
+}
 
 impl Backup {
-    pub(crate) fn run(
-        &self,
-        variables: &Context,
-    ) -> Result<((ExitStatus, String, String), Result<(), SaveLogError>), BackupRunError> {
-        let (context, command, stdout, stderr) =
-            self.compile(variables).change_context(BackupRunError)?;
+    pub(crate) fn run(&self, variables: &Context) -> Result<CommandOutput, BackupRunError> {
+        let Compiled {
+            context,
+            command,
+            stdout,
+            stderr,
+            changes,
+            combined,
+            log_header,
+            log_footer,
+            snapshot,
+        } = self.compile(variables).change_context(BackupRunError)?;
 
-        run_command(command, &stdout, &stderr, self.log.append, |log| {
+        let options = RunOptions {
+            stdout: self.log.split.then_some(stdout.as_str()),
+            stderr: self.log.split.then_some(stderr.as_str()),
+            combined: combined.as_deref(),
+            changes: changes.as_deref(),
+            header: log_header.as_deref(),
+            append: self.log.append,
+            stats: self.method.stats,
+            itemize: self.itemize(),
+            progress: self.output == OutLvl::Progress,
+        };
+        // Each stream gets its own copy of the context,
+        // only the variables of the line change from one line to the next
+        let line_format = || {
             let mut context = context.clone();
-            context.insert(LOG_BINDING.to_owned(), CtxString::literal(log));
-            self.log.format.evaluate(&context)
+            move |stream: Stream, line_no: usize, line: &str| match &self.log.line_format {
+                Some(format) => {
+                    context.extend(line_bindings(stream, line_no, line));
+                    format.evaluate(&context)
+                }
+                None => Ok(line.to_owned()),
+            }
+        };
+        let mut output = run_command(command, &options, line_format, |status, stats| {
+            let mut context = context.clone();
+            // Further references to `${log}` can't repeat the streamed output
+            context.insert(LOG_BINDING.to_owned(), CtxString::default());
+            context.extend(self.status_bindings(status));
+            context.extend(stats_bindings(stats));
+            log_footer.evaluate(&context)
         })
-        .change_context(BackupRunError)
-    }
+        .change_context(BackupRunError)?;
 
-    fn compile(
-        &self,
-        variables: &Context,
-    ) -> Result<(Context, Command, String, String), BackupCompileError> {
-        let mut context = variables.clone();
-        context
-            .entry(SOURCE_BINDING.to_owned())
-            .or_insert(self.source.clone());
-        context
-            .entry(TARGET_BINDING.to_owned())
-            .or_insert(self.target.clone());
+        if let Some(snapshot) = snapshot {
+            if !self.method.dry_run && self.classify(&output.status) != Outcome::Fail {
+                let name = snapshot
+                    .dir
+                    .file_name()
+                    .map(Path::new)
+                    .unwrap_or(&snapshot.dir);
+                output.link_result = replace_symlink(&snapshot.latest, name, self.method.sudo);
+            }
+        }
+        Ok(output)
+    }
 
-        let mut args = self.as_args(&context)?.into_iter();
+    pub(super) fn compile(&self, variables: &Context) -> Result<Compiled, BackupCompileError> {
+        let mut context = self.context(variables);
+        let snapshot = self.snapshot(&context)?;
+        if let Some(snapshot) = &snapshot {
+            context.insert(
+                SNAPSHOT_BINDING.to_owned(),
+                CtxString::literal(&snapshot.dir.to_string_lossy()),
+            );
+        }
+        let mut args = self.as_args(&context, snapshot.as_ref())?.into_iter();
         let mut command = Command::new(args.next().unwrap());
         command.args(args);
+        if self.method.stats {
+            command.arg(ARG_STATS);
+        }
+        if self.itemize() {
+            command.arg(ARG_ITEMIZE_CHANGES);
+        }
 
         let stdout = self
             .log
@@ -66,10 +164,190 @@ impl Backup {
             .evaluate(&context)
             .change_context(BackupCompileError)?;
 
-        Ok((context, command, stdout, stderr))
+        let changes = match &self.log.changes {
+            Some(changes) => Some(
+                changes
+                    .evaluate(&context)
+                    .change_context(BackupCompileError)?,
+            ),
+            None => None,
+        };
+
+        let combined = match &self.log.combined {
+            Some(combined) => Some(
+                combined
+                    .evaluate(&context)
+                    .change_context(BackupCompileError)?,
+            ),
+            None => None,
+        };
+
+        let (header, log_footer) = self.log_format(&context);
+        let log_header = match header {
+            Some(header) => Some(
+                header
+                    .evaluate(&context)
+                    .change_context(BackupCompileError)
+                    .attach_printable(
+                        "Only the part of log.format after ${log} can reference \
+                        the exit status and statistics of rsync",
+                    )?,
+            ),
+            None => None,
+        };
+
+        // Fail before running rsync instead of on every line
+        if let Some(line_format) = &self.log.line_format {
+            let mut line_context = context.clone();
+            line_context.extend(line_bindings(Stream::Stdout, 1, ""));
+            line_format
+                .evaluate(&line_context)
+                .change_context(BackupCompileError)
+                .attach_printable(ConfigKey(String::from("log.line_format")))?;
+        }
+
+        Ok(Compiled {
+            context,
+            command,
+            stdout,
+            stderr,
+            changes,
+            combined,
+            log_header,
+            log_footer,
+            snapshot,
+        })
+    }
+
+    /// `log.format` split around `${log}` into the parts before and after rsync's output,
+    /// following variables that reference it.
+    ///
+    /// Without a reference to `${log}`, the output isn't logged and all of it is a footer.
+    pub(super) fn log_format(&self, context: &Context) -> (Option<CtxString>, CtxString) {
+        match self.log.format.split_at_variable(context, LOG_BINDING) {
+            Some((header, footer)) => (Some(header), footer),
+            None => (None, self.log.format.clone()),
+        }
+    }
+
+    /// Whether rsync lists every changed file, which a change list needs
+    fn itemize(&self) -> bool {
+        self.method.itemize || self.log.changes.is_some()
+    }
+
+    /// Where this run goes in snapshot mode
+    fn snapshot(&self, context: &Context) -> Result<Option<Snapshot>, BackupCompileError> {
+        if self.method.mode != Mode::Snapshot {
+            return Ok(None);
+        }
+        let target = self
+            .target
+            .evaluate(context)
+            .change_context(BackupCompileError)?;
+        if is_remote(&target) {
+            return Err(Report::new(BackupCompileError)
+                .attach_printable(ConfigKey(String::from("method.mode")))
+                .attach_printable(REMOTE_SNAPSHOT));
+        }
+        let name = self
+            .method
+            .snapshot_name
+            .evaluate(context)
+            .change_context(BackupCompileError)?;
+        if let Some(problem) = invalid_snapshot_name(&name) {
+            return Err(Report::new(BackupCompileError)
+                .attach_printable(ConfigKey(String::from("method.snapshot_name")))
+                .attach_printable(problem));
+        }
+        Ok(Some(Snapshot {
+            dir: Path::new(&target).join(name),
+            latest: Path::new(&target).join(LATEST_SNAPSHOT),
+        }))
+    }
+
+    /// Estimate how many bytes a run would transfer, with a dry run of rsync
+    pub(super) fn estimate_transfer(&self, context: &Context) -> Result<u64, CheckError> {
+        let snapshot = self.snapshot(context).change_context(CheckError)?;
+        let args = self
+            .dry_run_args(context, snapshot.as_ref(), &[ARG_STATS])
+            .change_context(CheckError)?;
+
+        let output = Command::new(&args[0])
+            .args(&args[1..])
+            .output()
+            .change_context(CheckError)
+            .attach_printable("Failed to run rsync to estimate the transfer size")?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if self.classify(&output.status) == Outcome::Fail {
+            return Err(Report::new(CheckError)
+                .attach_printable("Failed to estimate the transfer size")
+                .attach_printable(describe_status(&output.status))
+                .attach_printable(String::from_utf8_lossy(&output.stderr).trim().to_owned()));
+        }
+        let stats = TransferStats::parse(&stdout);
+        stats.map(|s| s.total_transferred_size).ok_or_else(|| {
+            Report::new(CheckError).attach_printable("rsync didn't report the transfer size")
+        })
+    }
+
+    /// Arguments for a dry run with extra `options`, regardless of `dry_run` and `output`
+    pub(super) fn dry_run_args(
+        &self,
+        context: &Context,
+        snapshot: Option<&Snapshot>,
+        options: &[&str],
+    ) -> Result<Vec<String>, BackupCompileError> {
+        let mut args = self.as_args(context, snapshot)?;
+        let output = [ARG_QUIET, ARG_VERBOSE, ARG_PROGRESS, ARG_NO_INC_RECURSIVE];
+        args.retain(|arg| arg != ARG_DRY_RUN && !output.contains(&arg.as_str()));
+        let position = args
+            .iter()
+            .position(|arg| arg == COMMAND_RSYNC)
+            .unwrap_or_default()
+            + 1;
+        let options = [ARG_DRY_RUN].iter().chain(options).map(|o| o.to_string());
+        args.splice(position..position, options);
+        Ok(args)
     }
 
-    fn as_args(&self, context: &Context) -> Result<Vec<String>, BackupCompileError> {
+    /// Variables describing how rsync exited, available to `log.format`
+    fn status_bindings(&self, status: &ExitStatus) -> [(String, CtxString); 3] {
+        let code = status.code().map(|c| c.to_string()).unwrap_or_default();
+        [
+            (STATUS_BINDING.to_owned(), CtxString::literal(&code)),
+            (
+                STATUS_MEANING_BINDING.to_owned(),
+                CtxString::literal(&describe_status(status)),
+            ),
+            (
+                OUTCOME_BINDING.to_owned(),
+                CtxString::literal(&self.classify(status).to_string()),
+            ),
+        ]
+    }
+
+    /// The shared variables, extended by this backup's `source`, `target` and `name`
+    pub(super) fn context(&self, variables: &Context) -> Context {
+        let mut context = variables.clone();
+        context
+            .entry(SOURCE_BINDING.to_owned())
+            .or_insert(self.source.clone());
+        context
+            .entry(TARGET_BINDING.to_owned())
+            .or_insert(self.target.clone());
+        if let Some(name) = &self.name {
+            context
+                .entry(NAME_BINDING.to_owned())
+                .or_insert(CtxString::literal(name));
+        }
+        context
+    }
+
+    fn as_args(
+        &self,
+        context: &Context,
+        snapshot: Option<&Snapshot>,
+    ) -> Result<Vec<String>, BackupCompileError> {
         let mut args = Vec::new();
 
         if self.method.sudo {
@@ -85,7 +363,8 @@ impl Backup {
         match self.output {
             OutLvl::Quiet => args.push(ARG_QUIET.to_owned()),
             OutLvl::Verbose => args.push(ARG_VERBOSE.to_owned()),
-            _ => (),
+            OutLvl::Progress => args.extend([ARG_PROGRESS, ARG_NO_INC_RECURSIVE].map(String::from)),
+            OutLvl::Default => (),
         }
         for exclude in &self.exclude {
             args.push(format!(
@@ -96,15 +375,65 @@ impl Backup {
                     .change_context(BackupCompileError)?
             ))
         }
-        args.extend([
+        if let Some(link_dest) = snapshot.and_then(Snapshot::link_dest) {
+            args.push(format!("{}={}", ARG_LINK_DEST, link_dest.display()));
+        }
+        args.push(
             self.source
                 .evaluate(context)
                 .change_context(BackupCompileError)?,
-            self.target
+        );
+        args.push(match snapshot {
+            Some(snapshot) => snapshot.dir.to_string_lossy().into_owned(),
+            None => self
+                .target
                 .evaluate(context)
                 .change_context(BackupCompileError)?,
-        ]);
+        });
 
         Ok(args)
     }
 }
+
+/// Variables for each of rsync's transfer statistics, like `stats.files_transferred`.
+/// They're empty if rsync didn't print any.
+pub(super) fn stats_bindings(stats: Option<&TransferStats>) -> Vec<(String, CtxString)> {
+    let fields = stats.cloned().unwrap_or_default().fields();
+    Vec::from_iter(fields.into_iter().map(|(name, value)| {
+        let value = match stats {
+            Some(_) => CtxString::literal(&value),
+            None => CtxString::default(),
+        };
+        (format!("{}.{}", STATS_BINDING, name), value)
+    }))
+}
+
+/// Why a snapshot can't be called `name`, if it can't
+pub(super) fn invalid_snapshot_name(name: &str) -> Option<String> {
+    match name.is_empty() || name.contains('/') || name == LATEST_SNAPSHOT {
+        true => Some(format!(
+            "{:?} is not a valid snapshot name, it has to be a plain file name other than {:?}",
+            name, LATEST_SNAPSHOT
+        )),
+        false => None,
+    }
+}
+
+/// Variables describing a line of output, available to `log.line_format`
+pub(super) fn line_bindings(
+    stream: Stream,
+    line_no: usize,
+    line: &str,
+) -> [(String, CtxString); 3] {
+    [
+        (LINE_BINDING.to_owned(), CtxString::literal(line)),
+        (
+            LINE_NO_BINDING.to_owned(),
+            CtxString::literal(&line_no.to_string()),
+        ),
+        (
+            STREAM_BINDING.to_owned(),
+            CtxString::literal(&stream.to_string()),
+        ),
+    ]
+}
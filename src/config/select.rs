@@ -0,0 +1,66 @@
+use error_stack::{Report, Result};
+
+use super::{error::SelectError, Backup};
+
+/// Which backups a subcommand should act on
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Selection {
+    /// Only select backups with these names, select all if empty
+    pub only: Vec<String>,
+    /// Never select backups with these names
+    pub skip: Vec<String>,
+    /// Only select backups with at least one of these tags, select all if empty
+    pub tags: Vec<String>,
+    /// Never select backups with any of these tags
+    pub exclude_tags: Vec<String>,
+}
+
+impl Selection {
+    /// Select backups out of `runners`, keeping their order.
+    ///
+    /// Fails if a given name or tag doesn't belong to any backup.
+    pub fn apply<'a>(&self, runners: &'a [Backup]) -> Result<Vec<&'a Backup>, SelectError> {
+        let names = Vec::from_iter(runners.iter().filter_map(Backup::name));
+        check_known("name", self.only.iter().chain(&self.skip), &names)?;
+
+        let mut tags = Vec::from_iter(runners.iter().flat_map(|r| r.tags()).map(String::as_str));
+        tags.sort();
+        tags.dedup();
+        check_known("tag", self.tags.iter().chain(&self.exclude_tags), &tags)?;
+
+        Ok(runners
+            .iter()
+            .filter(|runner| self.selects(runner))
+            .collect())
+    }
+
+    fn selects(&self, runner: &Backup) -> bool {
+        let has_name =
+            |names: &[String]| runner.name().is_some_and(|n| names.iter().any(|m| m == n));
+        let has_tag = |tags: &[String]| runner.tags().iter().any(|t| tags.contains(t));
+
+        (self.only.is_empty() || has_name(&self.only))
+            && !has_name(&self.skip)
+            && (self.tags.is_empty() || has_tag(&self.tags))
+            && !has_tag(&self.exclude_tags)
+    }
+}
+
+fn check_known<'a>(
+    kind: &str,
+    given: impl Iterator<Item = &'a String>,
+    valid: &[&str],
+) -> Result<(), SelectError> {
+    let unknown = Vec::from_iter(given.filter(|n| !valid.contains(&n.as_str())));
+    if unknown.is_empty() {
+        return Ok(());
+    }
+
+    let valid = match valid.is_empty() {
+        true => format!("No backup has a {}", kind),
+        false => format!("Valid {}s are: {}", kind, valid.join(", ")),
+    };
+    Err(Report::new(SelectError)
+        .attach_printable(format!("Unknown backup {}(s): {:?}", kind, unknown))
+        .attach_printable(valid))
+}
@@ -0,0 +1,120 @@
+use serde::Deserialize;
+
+use crate::term::Style;
+
+// --- Deserialized Settings
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub(super) struct SettingsConfig {
+    strict: Option<bool>,
+    run: Option<RunSettingsConfig>,
+    preview: Option<PreviewSettingsConfig>,
+}
+
+impl SettingsConfig {
+    pub(super) fn build(&self) -> Settings {
+        Settings {
+            strict: self.strict.unwrap_or(Settings::default().strict),
+            run: self.run.clone().unwrap_or_default().build(),
+            preview: self.preview.clone().unwrap_or_default().build(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub(super) struct RunSettingsConfig {
+    pretty: Option<bool>,
+    colors: Option<bool>,
+}
+
+impl RunSettingsConfig {
+    fn build(&self) -> RunSettings {
+        let default = RunSettings::default();
+        RunSettings {
+            pretty: self.pretty.unwrap_or(default.pretty),
+            colors: self.colors.unwrap_or(default.colors),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub(super) struct PreviewSettingsConfig {
+    interactive: Option<bool>,
+    pretty: Option<bool>,
+    colors: Option<bool>,
+}
+
+impl PreviewSettingsConfig {
+    fn build(&self) -> PreviewSettings {
+        let default = PreviewSettings::default();
+        PreviewSettings {
+            interactive: self.interactive.unwrap_or(default.interactive),
+            pretty: self.pretty.unwrap_or(default.pretty),
+            colors: self.colors.unwrap_or(default.colors),
+        }
+    }
+}
+
+// --- Finalized Settings
+
+#[derive(Clone, Debug)]
+pub(crate) struct Settings {
+    /// Reject unknown keys instead of only warning about them
+    pub strict: bool,
+    pub run: RunSettings,
+    pub preview: PreviewSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            strict: true,
+            run: RunSettings::default(),
+            preview: PreviewSettings::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct RunSettings {
+    pub pretty: bool,
+    pub colors: bool,
+}
+
+impl Default for RunSettings {
+    fn default() -> Self {
+        RunSettings {
+            pretty: true,
+            colors: true,
+        }
+    }
+}
+
+impl RunSettings {
+    pub fn style(&self) -> Style {
+        Style::new(self.pretty, self.colors)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct PreviewSettings {
+    pub interactive: bool,
+    pub pretty: bool,
+    pub colors: bool,
+}
+
+impl Default for PreviewSettings {
+    fn default() -> Self {
+        PreviewSettings {
+            interactive: false,
+            pretty: true,
+            colors: true,
+        }
+    }
+}
+
+impl PreviewSettings {
+    pub fn style(&self) -> Style {
+        Style::new(self.pretty, self.colors)
+    }
+}
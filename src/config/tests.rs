@@ -0,0 +1,708 @@
+use std::path::PathBuf;
+
+use crate::{
+    fs::{PathProblem, TempDir},
+    runner::Stream,
+};
+
+use super::*;
+
+fn build(config: &str) -> Result<(Settings, Context, Vec<Backup>), ConfigBuildError> {
+    Config::from_str(config).unwrap().build()
+}
+
+const NAMED: &str = r#"
+[[run]]
+name = "home"
+source = "a"
+target = "b"
+
+[[run]]
+name = "root"
+source = "c"
+target = "d"
+
+[[run]]
+source = "e"
+target = "f"
+"#;
+
+#[test]
+fn selecting() {
+    let (_, _, runners) = build(NAMED).unwrap();
+    let names = |selection: &Selection| {
+        Vec::from_iter(selection.apply(&runners).unwrap().iter().map(|r| r.name()))
+    };
+
+    assert_eq!(
+        names(&Selection::default()),
+        vec![Some("home"), Some("root"), None]
+    );
+    assert_eq!(
+        names(&Selection {
+            only: vec![String::from("root")],
+            ..Default::default()
+        }),
+        vec![Some("root")]
+    );
+    assert_eq!(
+        names(&Selection {
+            skip: vec![String::from("root")],
+            ..Default::default()
+        }),
+        vec![Some("home"), None]
+    );
+    assert!(Selection {
+        only: vec![String::from("nope")],
+        ..Default::default()
+    }
+    .apply(&runners)
+    .is_err());
+}
+
+#[test]
+fn duplicate_names() {
+    let config = r#"
+        [[run]]
+        name = "home"
+        source = "a"
+        target = "b"
+
+        [[run]]
+        name = "home"
+        source = "c"
+        target = "d"
+    "#;
+    assert!(build(config).is_err());
+}
+
+#[test]
+fn selecting_tags() {
+    let config = r#"
+        [template]
+        tags = ["daily"]
+
+        [[run]]
+        name = "home"
+        source = "a"
+        target = "b"
+
+        [[run]]
+        name = "offsite"
+        tags = ["daily", "offsite"]
+        source = "c"
+        target = "d"
+
+        [[run]]
+        name = "laptop"
+        tags = ["laptop-only"]
+        source = "e"
+        target = "f"
+    "#;
+    let (_, _, runners) = build(config).unwrap();
+    let names = |tags: &[&str], exclude_tags: &[&str]| {
+        let selection = Selection {
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            exclude_tags: exclude_tags.iter().map(|t| t.to_string()).collect(),
+            ..Default::default()
+        };
+        Vec::from_iter(selection.apply(&runners).unwrap().iter().map(|r| r.name()))
+    };
+
+    assert_eq!(names(&["daily"], &[]), vec![Some("home"), Some("offsite")]);
+    assert_eq!(names(&["daily"], &["offsite"]), vec![Some("home")]);
+    assert_eq!(names(&[], &["daily"]), vec![Some("laptop")]);
+    assert!(Selection {
+        tags: vec![String::from("weekly")],
+        ..Default::default()
+    }
+    .apply(&runners)
+    .is_err());
+}
+
+#[test]
+fn unknown_keys() {
+    let config = r#"
+        [settings.run]
+        prety = true
+
+        [template.logs]
+        stdout = "out.log"
+
+        [[run]]
+        source = "a"
+        target = "b"
+
+        [[run]]
+        source = "c"
+        target = "d"
+        method.dryrun = true
+        retention.keep_dialy = 3
+    "#;
+    let (_, unknown) = Config::parse(config).unwrap();
+    let unknown = Vec::from_iter(
+        unknown
+            .into_iter()
+            .map(|key| (key.path, key.location, key.suggestion)),
+    );
+    assert_eq!(
+        unknown,
+        vec![
+            (
+                "settings.run.prety".to_owned(),
+                Some((3, 9)),
+                Some("pretty")
+            ),
+            ("template.logs".to_owned(), Some((5, 19)), Some("log")),
+            (
+                "run[1].method.dryrun".to_owned(),
+                Some((15, 16)),
+                Some("dry_run")
+            ),
+            (
+                "run[1].retention.keep_dialy".to_owned(),
+                Some((16, 19)),
+                Some("keep_daily")
+            ),
+        ]
+    );
+
+    assert!(Config::from_str(config).is_err());
+    let lenient = format!("[settings]\nstrict = false\n{}", config);
+    assert_eq!(Config::from_str(&lenient).unwrap().unknown_keys().len(), 4);
+}
+
+#[test]
+fn checking() {
+    let config = r#"
+        [template]
+        log.stdout = "${missing}.log"
+        log.format = "${stats.files_transferred} files, ${stats.nothing}"
+
+        [[run]]
+        source = "a"
+        target = "b"
+        exclude = ["${source}", "${other}"]
+    "#;
+    let (_, context, runners) = build(config).unwrap();
+    assert_eq!(runners[0].check(&context).len(), 3);
+
+    let snapshot = |target: &str, name: &str| {
+        let config = format!(
+            "[[run]]\nsource = \"a\"\ntarget = {:?}\n\
+            method.mode = \"snapshot\"\nmethod.snapshot_name = {:?}",
+            target, name
+        );
+        let (_, context, runners) = build(&config).unwrap();
+        runners[0].check(&context).len()
+    };
+    assert_eq!(snapshot("b", "${source}-%Y"), 0);
+    assert_eq!(snapshot("b", "${undefined}-%Y"), 1);
+    assert_eq!(snapshot("b", "latest"), 1);
+    assert_eq!(snapshot("b", "%Y/%m"), 1);
+    assert_eq!(snapshot("host:/b", "%Y"), 1);
+}
+
+#[test]
+fn locating_errors() {
+    let config = r#"
+[template.log]
+stdout = "${home"
+
+[[run]]
+source = "a"
+target = "b"
+
+[[run]]
+source = "c"
+target = "d"
+log.stdout = "out.log"
+exclude = ["x", "y%"]
+"#;
+    let located = |report: Report<ConfigBuildError>| {
+        report
+            .frames()
+            .filter_map(|frame| frame.downcast_ref::<String>())
+            .find(|s| s.starts_with("Set by"))
+            .map(|s| s.lines().next().unwrap().to_owned())
+    };
+
+    let error = build(config).unwrap_err();
+    assert_eq!(
+        located(error).as_deref(),
+        Some("Set by `template.log.stdout` at line 3, column 1:")
+    );
+
+    let config = config.replace("${home", "${home}");
+    let error = build(&config).unwrap_err();
+    assert_eq!(
+        located(error).as_deref(),
+        Some("Set by `run[1].exclude[1]` at line 13, column 17:")
+    );
+}
+
+#[test]
+fn building_each_backup() {
+    let config = r#"
+[[run]]
+source = "${a"
+target = "b"
+log.format = "100%"
+
+[[run]]
+source = "c"
+target = "${d"
+
+[[run]]
+source = "e"
+target = "f"
+"#;
+    let config = Config::from_str(config).unwrap();
+    let problems = Vec::from_iter(
+        config
+            .build_backups()
+            .into_iter()
+            .map(|result| result.map_or_else(|e| e.len(), |_| 0)),
+    );
+    assert_eq!(problems, vec![2, 1, 0]);
+    assert!(config.build().is_err());
+}
+
+#[test]
+fn preflight_checks() {
+    let dir = TempDir::new("preflight");
+    let source = dir.join("source");
+    std::fs::create_dir_all(&source).unwrap();
+    let config = |target: &str, create: bool| {
+        format!(
+            "[[run]]\nsource = {:?}\ntarget = {:?}\ncreate_target = {}\n\
+            log.stdout = {:?}\nlog.stderr = {:?}",
+            source,
+            dir.join(target),
+            create,
+            dir.join("logs/out.log"),
+            dir.join("logs/err.log"),
+        )
+    };
+    let preflight = |config: &str| {
+        let (_, context, runners) = build(config).unwrap();
+        runners[0].preflight(&context)
+    };
+
+    let error = preflight(&config("missing/target", false)).unwrap_err();
+    assert_eq!(error.downcast_ref(), Some(&PathProblem::Missing));
+    assert!(!dir.join("logs").exists());
+
+    preflight(&config("missing/target", true)).unwrap();
+    assert!(dir.join("missing/target").is_dir());
+    assert!(dir.join("logs").is_dir());
+}
+
+#[test]
+fn snapshot_args() {
+    let dir = TempDir::new("snapshot");
+    std::fs::create_dir_all(dir.join("old")).unwrap();
+    let config = format!(
+        "[[run]]\nname = \"home\"\nsource = \"a\"\ntarget = {:?}\n\
+        method.mode = \"snapshot\"\nmethod.snapshot_name = \"${{name}}-new\"",
+        dir.path()
+    );
+    let args = |config: &str| {
+        let (_, context, runners) = build(config).unwrap();
+        let compiled = runners[0].compile(&context).unwrap();
+        Vec::from_iter(
+            compiled
+                .command
+                .get_args()
+                .map(|a| a.to_string_lossy().into_owned()),
+        )
+    };
+
+    let target = dir.join("home-new").to_string_lossy().into_owned();
+    assert_eq!(args(&config), vec!["-aAx", "a", &target]);
+
+    std::os::unix::fs::symlink("old", dir.join("latest")).unwrap();
+    let old = std::fs::canonicalize(dir.join("old")).unwrap();
+    let link_dest = format!("--link-dest={}", old.display());
+    assert_eq!(args(&config), vec!["-aAx", &link_dest, "a", &target]);
+}
+
+#[test]
+fn retention() {
+    let dates = Vec::from_iter(
+        [
+            "2024-03-10 12:00",
+            "2024-03-10 08:00",
+            "2024-03-09 12:00",
+            "2024-03-02 12:00",
+            "2024-02-20 12:00",
+            "2024-01-05 12:00",
+            "2023-12-31 12:00",
+        ]
+        .iter()
+        .map(|d| chrono::NaiveDateTime::parse_from_str(d, "%Y-%m-%d %H:%M").unwrap()),
+    );
+    let keep = |retention: Retention| {
+        let keep = retention.keep(&dates);
+        String::from_iter(keep.iter().map(|k| if *k { 'k' } else { '-' }))
+    };
+
+    assert!(!Retention::default().is_set());
+    assert_eq!(keep(Retention::default()), "-------");
+    let last = Retention {
+        keep_last: Some(2),
+        ..Default::default()
+    };
+    assert_eq!(keep(last), "kk-----");
+    let daily = Retention {
+        keep_daily: Some(3),
+        ..Default::default()
+    };
+    assert_eq!(keep(daily), "k-kk---");
+    let weekly = Retention {
+        keep_weekly: Some(2),
+        ..Default::default()
+    };
+    assert_eq!(keep(weekly), "k--k---");
+    let monthly = Retention {
+        keep_monthly: Some(12),
+        keep_yearly: Some(2),
+        ..Default::default()
+    };
+    assert_eq!(keep(monthly), "k---kkk");
+}
+
+#[test]
+fn pruning() {
+    let dir = TempDir::new("prune");
+    for name in [
+        "2024-03-10_1200",
+        "2024-03-09_1200",
+        "2024-03-08_1200",
+        "notes",
+    ] {
+        std::fs::create_dir_all(dir.join(name)).unwrap();
+    }
+    std::fs::write(dir.join("2024-03-07_1200"), "").unwrap();
+    std::os::unix::fs::symlink("2024-03-08_1200", dir.join("latest")).unwrap();
+    let config = format!(
+        "[[run]]\nsource = \"a\"\ntarget = {:?}\n\
+        method.mode = \"snapshot\"\nretention.keep_last = 1",
+        dir.path()
+    );
+    let (_, context, runners) = build(&config).unwrap();
+    assert!(runners[0].prunes());
+
+    let snapshots = runners[0].snapshots(&context).unwrap();
+    let found = Vec::from_iter(snapshots.iter().map(|s| {
+        let name = s.path.file_name().unwrap().to_string_lossy().into_owned();
+        (name, s.keep, s.latest)
+    }));
+    assert_eq!(
+        found,
+        vec![
+            (String::from("2024-03-10_1200"), true, false),
+            (String::from("2024-03-09_1200"), false, false),
+            (String::from("2024-03-08_1200"), true, true),
+        ]
+    );
+
+    assert!(runners[0].remove_snapshot(&snapshots[0]).is_err());
+    runners[0].remove_snapshot(&snapshots[1]).unwrap();
+    assert!(!dir.join("2024-03-09_1200").exists());
+    assert!(dir.join("notes").exists());
+}
+
+#[test]
+fn restore_args() {
+    let dir = TempDir::new("restore");
+    std::fs::create_dir_all(dir.join("2024-03-10_1200/home/docs")).unwrap();
+    std::fs::create_dir_all(dir.join("2024-03-12_1200/home")).unwrap();
+    std::os::unix::fs::symlink("2024-03-12_1200", dir.join("latest")).unwrap();
+    let config = format!(
+        "[[run]]\nsource = \"/home\"\ntarget = {:?}\nexclude = [\"*.tmp\"]\n\
+        method.mode = \"snapshot\"\nmethod.delete = true",
+        dir.path()
+    );
+    let (_, context, runners) = build(&config).unwrap();
+    let args = |restore: &Restore, dry_run: bool| {
+        let command = runners[0].restore(&context, restore).unwrap();
+        Vec::from_iter(
+            command
+                .command(dry_run)
+                .get_args()
+                .map(|a| a.to_string_lossy().into_owned()),
+        )
+    };
+    let path = |p: &str| dir.join(p).to_string_lossy().into_owned();
+
+    let latest = format!("{}/", path("latest/home"));
+    assert_eq!(
+        args(&Restore::default(), true),
+        vec![
+            "-aAx",
+            "--exclude=*.tmp",
+            "--dry-run",
+            "--verbose",
+            &latest,
+            "/home/"
+        ]
+    );
+
+    let restore = Restore {
+        snapshot: Some(String::from("2024-03-10")),
+        to: Some(PathBuf::from("/tmp/restored")),
+        path: Some(PathBuf::from("docs")),
+        delete: true,
+    };
+    let docs = format!("{}/", path("2024-03-10_1200/home/docs"));
+    assert_eq!(
+        args(&restore, false),
+        vec![
+            "-aAx",
+            "--delete",
+            "--exclude=*.tmp",
+            &docs,
+            "/tmp/restored/docs/"
+        ]
+    );
+
+    let restore = |snapshot: &str, path: &str| Restore {
+        snapshot: Some(snapshot.to_owned()),
+        path: Some(PathBuf::from(path)),
+        ..Default::default()
+    };
+    assert!(runners[0].restore(&context, &restore("2023", "")).is_err());
+    assert!(runners[0]
+        .restore(&context, &restore("2024", "../etc"))
+        .is_err());
+}
+
+#[test]
+fn verify_args() {
+    let config = "[[run]]\nsource = \"a\"\ntarget = \"b\"\noutput = \"verbose\"\n\
+        method.dry_run = true\nmethod.sudo = true";
+    let (_, context, runners) = build(config).unwrap();
+    let options = [run::ARG_CHECKSUM, crate::rsync::ARG_ITEMIZE_CHANGES];
+    assert_eq!(
+        runners[0].dry_run_args(&context, None, &options).unwrap(),
+        vec![
+            "sudo",
+            "rsync",
+            "--dry-run",
+            "--checksum",
+            "--itemize-changes",
+            "-aAx",
+            "a",
+            "b"
+        ]
+    );
+}
+
+#[test]
+fn reporting_args() {
+    let args = |config: &str| {
+        let (_, context, runners) = build(config).unwrap();
+        let compiled = runners[0].compile(&context).unwrap();
+        Vec::from_iter(
+            compiled
+                .command
+                .get_args()
+                .map(|a| a.to_string_lossy().into_owned()),
+        )
+    };
+    let run = "[[run]]\nsource = \"a\"\ntarget = \"b\"\n";
+
+    assert_eq!(args(run), vec!["-aAx", "a", "b"]);
+    assert_eq!(
+        args(&format!("{run}method.stats = true")),
+        vec!["-aAx", "a", "b", "--stats"]
+    );
+    assert_eq!(
+        args(&format!("{run}log.changes = \"changes.txt\"")),
+        vec!["-aAx", "a", "b", "--itemize-changes"]
+    );
+}
+
+#[test]
+fn splitting_log_format() {
+    let compile = |format: &str| {
+        let config = format!(
+            "[variables]\nentry = \"${{target}}: ${{log}}\"\n\
+            [[run]]\nsource = \"a\"\ntarget = \"b\"\nlog.format = \"{format}\""
+        );
+        let (_, context, runners) = build(&config).unwrap();
+        let problems = runners[0].check(&context).len();
+        let compiled = runners[0].compile(&context);
+        (
+            compiled.map(|c| (c.log_header, c.log_footer)).ok(),
+            problems,
+        )
+    };
+
+    assert_eq!(
+        compile("${source} to ${target}:\\n${log}\\n${outcome}"),
+        (
+            Some((
+                Some(String::from("a to b:\n")),
+                CtxString::new("\n${outcome}").unwrap()
+            )),
+            0
+        )
+    );
+    assert_eq!(
+        compile("${status}"),
+        (Some((None, CtxString::new("${status}").unwrap())), 0)
+    );
+    assert_eq!(compile("${status}\\n${log}"), (None, 1));
+    assert_eq!(
+        compile("[${entry}]"),
+        (
+            Some((Some(String::from("[b: ")), CtxString::new("]").unwrap())),
+            0
+        )
+    );
+}
+
+#[test]
+fn formatting_lines() {
+    let config = r#"
+        [[run]]
+        source = "a"
+        target = "b"
+        log.line_format = "${stream} ${line_no}: ${line} (${target})"
+
+        [[run]]
+        source = "a"
+        target = "b"
+        log.line_format = "${line} ${outcome}"
+    "#;
+    let (_, context, runners) = build(config).unwrap();
+    let line_format = runners[0].log.line_format.as_ref().unwrap();
+    let mut line_context = runners[0].context(&context);
+    line_context.extend(run::line_bindings(Stream::Stderr, 3, "50% done"));
+    assert_eq!(
+        line_format.evaluate(&line_context).unwrap(),
+        "stderr 3: 50% done (b)"
+    );
+
+    assert!(runners[0].compile(&context).is_ok());
+    assert!(runners[1].compile(&context).is_err());
+    assert_eq!(runners[1].check(&context).len(), 1);
+}
+
+#[test]
+fn combining_logs() {
+    let config = r#"
+        [[run]]
+        source = "a"
+        target = "b"
+        log.combined = "${target}/all.log"
+
+        [[run]]
+        source = "a"
+        target = "b"
+        log.split = false
+        log.combined = "${missing}.log"
+
+        [[run]]
+        source = "a"
+        target = "b"
+        log.split = false
+    "#;
+    let (_, context, runners) = build(config).unwrap();
+    assert!(runners[0].log.split);
+    assert_eq!(
+        runners[0].compile(&context).unwrap().combined.as_deref(),
+        Some("b/all.log")
+    );
+    assert!(!runners[1].log.split);
+    assert_eq!(runners[1].check(&context).len(), 1);
+    // Nothing would be logged at all
+    assert_eq!(runners[2].check(&context).len(), 1);
+}
+
+#[test]
+fn classifying() {
+    use std::os::unix::process::ExitStatusExt;
+
+    let config = r#"
+        [template]
+        method.warn_codes = [23, 24]
+
+        [[run]]
+        source = "a"
+        target = "b"
+        method.warn_codes = [24]
+
+        [[run]]
+        source = "a"
+        target = "b"
+        method.accept_codes = [0, 24]
+
+        [[run]]
+        source = "a"
+        target = "b"
+    "#;
+    let (_, _, runners) = build(config).unwrap();
+    let classify = |runner: &Backup, code: i32| runner.classify(&ExitStatus::from_raw(code << 8));
+
+    assert_eq!(classify(&runners[0], 0), Outcome::Ok);
+    assert_eq!(classify(&runners[0], 24), Outcome::Warn);
+    assert_eq!(classify(&runners[0], 23), Outcome::Fail);
+    assert_eq!(classify(&runners[0], 12), Outcome::Fail);
+
+    // Accepting a code takes precedence over warning about it
+    assert_eq!(classify(&runners[1], 24), Outcome::Ok);
+    assert_eq!(classify(&runners[1], 23), Outcome::Warn);
+
+    assert_eq!(classify(&runners[2], 23), Outcome::Warn);
+    assert_eq!(classify(&runners[2], 24), Outcome::Warn);
+    assert_eq!(classify(&runners[2], 0), Outcome::Ok);
+
+    // Killed by a signal, without an exit code
+    assert_eq!(runners[2].classify(&ExitStatus::from_raw(9)), Outcome::Fail);
+}
+
+#[test]
+fn running_in_order() {
+    use FailurePolicy::{Abort, AbortRemaining, Continue};
+
+    // Each backup is its policy and whether it fails
+    let run = |backups: &[(FailurePolicy, bool)]| {
+        run_in_order(
+            backups,
+            |(policy, _)| *policy,
+            |i, (_, failed)| (i, *failed),
+        )
+    };
+
+    let runs = run(&[(Abort, false), (AbortRemaining, false), (Continue, false)]);
+    assert_eq!(runs.results, vec![0, 1, 2]);
+    assert!(runs.succeeded());
+
+    let runs = run(&[(Continue, true), (Continue, false), (Continue, true)]);
+    assert_eq!(runs.results, vec![0, 1, 2]);
+    assert_eq!((runs.skipped, runs.aborted), (0, false));
+    assert!(!runs.succeeded());
+
+    let runs = run(&[(Continue, false), (AbortRemaining, true), (Continue, false)]);
+    assert_eq!(runs.results, vec![0, 1]);
+    assert_eq!((runs.skipped, runs.aborted), (1, false));
+    assert!(!runs.succeeded());
+
+    let runs = run(&[(Abort, true), (Continue, false), (Continue, false)]);
+    assert_eq!(runs.results, vec![0]);
+    assert_eq!((runs.skipped, runs.aborted), (2, true));
+    assert!(!runs.succeeded());
+
+    // Only the policy of the failing backup counts
+    let runs = run(&[(Abort, false), (Continue, true), (AbortRemaining, false)]);
+    assert_eq!(runs.results, vec![0, 1, 2]);
+    assert_eq!((runs.skipped, runs.aborted), (0, false));
+    assert!(!runs.succeeded());
+
+    let runs = run(&[]);
+    assert!(runs.results.is_empty());
+    assert!(runs.succeeded());
+}
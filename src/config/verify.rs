@@ -0,0 +1,57 @@
+use std::{fs::canonicalize, path::Path, process::Command};
+
+use error_stack::{Report, Result, ResultExt};
+
+use crate::{
+    ctx_string::Context,
+    rsync::{describe_status, itemized_changes, Change, Item, Outcome, ARG_ITEMIZE_CHANGES},
+};
+
+use super::{
+    error::VerifyError,
+    run::{Snapshot, ARG_CHECKSUM},
+    Backup, Mode, LATEST_SNAPSHOT,
+};
+
+impl Backup {
+    /// Compare source and target by checksum, with a dry run of rsync.
+    ///
+    /// Returns every file missing from or differing in the target,
+    /// which is the latest snapshot in snapshot mode.
+    pub(crate) fn verify(&self, variables: &Context) -> Result<Vec<Item>, VerifyError> {
+        let context = self.context(variables);
+        let snapshot = match self.method.mode {
+            Mode::Mirror => None,
+            Mode::Snapshot => {
+                let target = self.target.evaluate(&context).change_context(VerifyError)?;
+                let latest = Path::new(&target).join(LATEST_SNAPSHOT);
+                let dir = canonicalize(&latest)
+                    .change_context(VerifyError)
+                    .attach_printable_lazy(|| format!("No snapshot to verify at {:?}", latest))?;
+                Some(Snapshot { dir, latest })
+            }
+        };
+        let args = self
+            .dry_run_args(
+                &context,
+                snapshot.as_ref(),
+                &[ARG_CHECKSUM, ARG_ITEMIZE_CHANGES],
+            )
+            .change_context(VerifyError)?;
+
+        let output = Command::new(&args[0])
+            .args(&args[1..])
+            .output()
+            .change_context(VerifyError)
+            .attach_printable("Failed to run rsync")?;
+        if self.classify(&output.status) == Outcome::Fail {
+            return Err(Report::new(VerifyError)
+                .attach_printable(describe_status(&output.status))
+                .attach_printable(String::from_utf8_lossy(&output.stderr).trim().to_owned()));
+        }
+
+        let mut items = itemized_changes(&String::from_utf8_lossy(&output.stdout));
+        items.retain(|item| item.change != Change::Attributes);
+        Ok(items)
+    }
+}
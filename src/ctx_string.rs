@@ -1,16 +1,18 @@
 use std::collections::HashMap;
 
-use chrono::Local;
+use chrono::{
+    format::{self, Parsed, StrftimeItems},
+    Local, NaiveDateTime, NaiveTime,
+};
 use error_stack::{Report, Result};
 
-use self::{
-    error::{CtxParseError, CtxWriteError},
-    parser::ctx_str as parse_ctx_str,
-};
+use self::{error::CtxWriteError, parser::ctx_str as parse_ctx_str};
 
 mod error;
 mod parser;
 
+pub use error::CtxParseError;
+
 pub type Context = HashMap<String, CtxString>;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -25,13 +27,27 @@ pub struct CtxString(Vec<Token>);
 
 impl CtxString {
     pub fn new(string: &str) -> Result<CtxString, CtxParseError> {
-        let (i, s) = parse_ctx_str(string).or(Err(CtxParseError))?;
-        if !i.is_empty() {
-            Err(Report::new(CtxParseError)
-                .attach_printable(format!("Couldn't parse complete input: {:?}", i)))
-        } else {
-            Ok(s)
+        let (rest, s) = parse_ctx_str(string).or(Err(CtxParseError))?;
+        if rest.is_empty() {
+            return Ok(s);
         }
+
+        let problem = match rest {
+            r if r.starts_with("${}") => "Empty variable name",
+            r if r.starts_with("${") => "Unterminated variable, expected `}`",
+            r if r.starts_with('$') => "Expected `{` after `$`, write `\\$` for a literal `$`",
+            "%" => "Dangling `%`, expected a date format after it",
+            "\\" => "Dangling `\\`, expected a character to escape",
+            _ => "Couldn't parse the rest of the input",
+        };
+        let column = string[..string.len() - rest.len()].chars().count();
+        Err(Report::new(CtxParseError).attach_printable(format!(
+            "{} at column {}:\n{}\n{}^",
+            problem,
+            column + 1,
+            string,
+            " ".repeat(column)
+        )))
     }
 
     pub fn literal(string: &str) -> CtxString {
@@ -39,19 +55,163 @@ impl CtxString {
     }
 
     pub fn evaluate(&self, context: &Context) -> Result<String, CtxWriteError> {
+        let date = |d: &str| Local::now().format(d).to_string();
+        self.evaluate_tracked(context, &mut Vec::new(), &str::to_owned, &date)
+    }
+
+    /// Parse the date back out of a string this format string evaluated to.
+    ///
+    /// Returns `None` if `s` doesn't match the format
+    /// or the format doesn't contain a full date.
+    pub fn parse_date(
+        &self,
+        context: &Context,
+        s: &str,
+    ) -> Result<Option<NaiveDateTime>, CtxWriteError> {
+        let escape = |l: &str| l.replace('%', "%%");
+        let format = self.evaluate_tracked(context, &mut Vec::new(), &escape, &str::to_owned)?;
+
+        let mut parsed = Parsed::new();
+        if format::parse(&mut parsed, s, StrftimeItems::new(&format)).is_err() {
+            return Ok(None);
+        }
+        let Ok(date) = parsed.to_naive_date() else {
+            return Ok(None);
+        };
+        let time = parsed.to_naive_time().unwrap_or(NaiveTime::MIN);
+        Ok(Some(date.and_time(time)))
+    }
+
+    /// Evaluate while keeping track of the chain of variables
+    /// currently being resolved, so recursion fails instead of overflowing.
+    ///
+    /// `literal` and `date` write the literals and datetime atoms.
+    fn evaluate_tracked(
+        &self,
+        context: &Context,
+        chain: &mut Vec<String>,
+        literal: &dyn Fn(&str) -> String,
+        date: &dyn Fn(&str) -> String,
+    ) -> Result<String, CtxWriteError> {
         self.0
             .iter()
             .map(|token| match token {
-                Token::Literal(s) => Ok(s.clone()),
-                Token::Var(v) => context
-                    .get(v)
-                    .map(|s| s.evaluate(context))
-                    .unwrap_or(Err(Report::new(CtxWriteError)
-                        .attach_printable(format!("Variable {:?} is not defined", v)))),
-                Token::DateTime(d) => Ok(Local::now().format(d).to_string()),
+                Token::Literal(s) => Ok(literal(s)),
+                Token::Var(v) if chain.contains(v) => {
+                    chain.push(v.clone());
+                    Err(Report::new(CtxWriteError)
+                        .attach_printable(format!("Recursive variable: {}", chain.join(" -> "))))
+                }
+                Token::Var(v) => {
+                    let s = context.get(v).ok_or_else(|| {
+                        Report::new(CtxWriteError)
+                            .attach_printable(format!("Variable {:?} is not defined", v))
+                    })?;
+                    chain.push(v.clone());
+                    let res = s.evaluate_tracked(context, chain, literal, date)?;
+                    chain.pop();
+                    Ok(res)
+                }
+                Token::DateTime(d) => Ok(date(d)),
             })
             .collect::<Result<String, _>>()
     }
+
+    /// Split this string around the first reference to `variable`,
+    /// or return `None` if it doesn't reference it.
+    ///
+    /// Variables of `context` that lead to `variable` are replaced by their
+    /// definitions, so their parts can end up on either side of the split.
+    pub fn split_at_variable(
+        &self,
+        context: &Context,
+        variable: &str,
+    ) -> Option<(CtxString, CtxString)> {
+        self.split_tracked(context, variable, &mut Vec::new())
+    }
+
+    /// Split while keeping track of the chain of variables being resolved,
+    /// recursive variables are skipped
+    fn split_tracked(
+        &self,
+        context: &Context,
+        variable: &str,
+        chain: &mut Vec<String>,
+    ) -> Option<(CtxString, CtxString)> {
+        for (at, token) in self.0.iter().enumerate() {
+            let Token::Var(v) = token else {
+                continue;
+            };
+            let (before, after) = match v == variable {
+                true => (CtxString::default(), CtxString::default()),
+                false => {
+                    let Some(s) = context.get(v).filter(|_| !chain.contains(v)) else {
+                        continue;
+                    };
+                    chain.push(v.clone());
+                    let split = s.split_tracked(context, variable, chain);
+                    chain.pop();
+                    match split {
+                        Some(split) => split,
+                        None => continue,
+                    }
+                }
+            };
+            let mut head = self.0[..at].to_vec();
+            head.extend(before.0);
+            let mut tail = after.0;
+            tail.extend_from_slice(&self.0[at + 1..]);
+            return Some((CtxString(head), CtxString(tail)));
+        }
+        None
+    }
+
+    /// Names of all variables referenced directly by this string
+    pub fn variables(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().filter_map(|token| match token {
+            Token::Var(v) => Some(v.as_str()),
+            _ => None,
+        })
+    }
+}
+
+/// Find a chain of variables in `context` that refers back to itself.
+///
+/// The returned path starts and ends with the same variable, e.g. `[a, b, a]`.
+pub fn find_cycle(context: &Context) -> Option<Vec<String>> {
+    let mut names = context.keys().collect::<Vec<_>>();
+    names.sort();
+
+    let mut done = Vec::new();
+    names
+        .into_iter()
+        .find_map(|name| visit(name, context, &mut Vec::new(), &mut done))
+}
+
+fn visit<'a>(
+    name: &'a str,
+    context: &'a Context,
+    chain: &mut Vec<&'a str>,
+    done: &mut Vec<&'a str>,
+) -> Option<Vec<String>> {
+    if let Some(start) = chain.iter().position(|n| *n == name) {
+        let mut cycle = Vec::from_iter(chain[start..].iter().map(|n| n.to_string()));
+        cycle.push(name.to_owned());
+        return Some(cycle);
+    }
+    if done.contains(&name) {
+        return None;
+    }
+
+    chain.push(name);
+    let cycle = context
+        .get(name)
+        .into_iter()
+        .flat_map(|s| s.variables())
+        .find_map(|var| visit(var, context, chain, done));
+    chain.pop();
+    done.push(name);
+    cycle
 }
 
 #[cfg(test)]
@@ -1,3 +1,5 @@
+use error_stack::{AttachmentKind, FrameKind};
+
 use super::*;
 
 #[test]
@@ -62,3 +64,112 @@ fn parsing() {
     assert!(CtxString::new("This ${var should end somewhere").is_err());
     assert!(CtxString::new("This is also not ok %").is_err());
 }
+
+fn context(vars: &[(&str, &str)]) -> Context {
+    vars.iter()
+        .map(|(k, v)| (k.to_string(), CtxString::new(v).unwrap()))
+        .collect()
+}
+
+#[test]
+fn evaluating() {
+    let ctx = context(&[("a", "A${b}"), ("b", "B")]);
+    assert_eq!(
+        CtxString::new("${a}${b}").unwrap().evaluate(&ctx).unwrap(),
+        "ABB"
+    );
+    assert!(CtxString::new("${c}").unwrap().evaluate(&ctx).is_err());
+}
+
+#[test]
+fn recursion() {
+    let ctx = context(&[("a", "${b}"), ("b", "${c}"), ("c", "${a}"), ("d", "${a}")]);
+    assert!(CtxString::new("${d}").unwrap().evaluate(&ctx).is_err());
+    assert_eq!(find_cycle(&ctx).unwrap(), vec!["a", "b", "c", "a"]);
+
+    let ctx = context(&[("a", "${a}")]);
+    assert_eq!(find_cycle(&ctx).unwrap(), vec!["a", "a"]);
+
+    let ctx = context(&[("a", "${b}${b}"), ("b", "${c}"), ("c", "c")]);
+    assert!(find_cycle(&ctx).is_none());
+    assert_eq!(
+        CtxString::new("${a}").unwrap().evaluate(&ctx).unwrap(),
+        "cc"
+    );
+}
+
+#[test]
+fn parse_errors() {
+    let printed = |s: &str| {
+        let report = CtxString::new(s).unwrap_err();
+        Vec::from_iter(report.frames().filter_map(|frame| match frame.kind() {
+            FrameKind::Attachment(AttachmentKind::Printable(p)) => Some(p.to_string()),
+            _ => None,
+        }))
+    };
+
+    assert_eq!(
+        printed("${home/backup"),
+        vec!["Unterminated variable, expected `}` at column 1:\n${home/backup\n^"]
+    );
+    assert_eq!(
+        printed("100%"),
+        vec!["Dangling `%`, expected a date format after it at column 4:\n100%\n   ^"]
+    );
+    assert_eq!(
+        printed("a ${} b"),
+        vec!["Empty variable name at column 3:\na ${} b\n  ^"]
+    );
+}
+
+#[test]
+fn parsing_dates() {
+    let ctx = context(&[("day", "%Y-%m-%d"), ("host", "50\\%")]);
+    let date = |f: &str, s: &str| {
+        let date = CtxString::new(f).unwrap().parse_date(&ctx, s).unwrap();
+        date.map(|d| d.format("%Y-%m-%d %H:%M").to_string())
+    };
+
+    assert_eq!(
+        date("%Y-%m-%d_%H%M", "2024-03-01_1830").as_deref(),
+        Some("2024-03-01 18:30")
+    );
+    assert_eq!(
+        date("${day}", "2024-03-01").as_deref(),
+        Some("2024-03-01 00:00")
+    );
+    assert_eq!(
+        date("${host}_${day}", "50%_2024-03-01").as_deref(),
+        Some("2024-03-01 00:00")
+    );
+    assert_eq!(date("%Y-%m-%d_%H%M", "2024-03-01"), None);
+    assert_eq!(date("%Y-%m-%d", "2024-03-01.old"), None);
+    assert_eq!(date("%H%M", "1830"), None);
+    assert!(CtxString::new("${none}")
+        .unwrap()
+        .parse_date(&ctx, "x")
+        .is_err());
+}
+
+#[test]
+fn splitting() {
+    let context = context(&[("entry", "<${log}> ${a}"), ("a", "${b}"), ("b", "${a}")]);
+    let split = |s: &str| {
+        CtxString::new(s)
+            .unwrap()
+            .split_at_variable(&context, "log")
+    };
+    let parts = |header: &str, footer: &str| {
+        Some((
+            CtxString::new(header).unwrap(),
+            CtxString::new(footer).unwrap(),
+        ))
+    };
+
+    assert_eq!(
+        split("${date}: ${log} (${status}) ${log}"),
+        parts("${date}: ", " (${status}) ${log}")
+    );
+    assert_eq!(split("${a}%Y${entry}!"), parts("${a}%Y<", "> ${a}!"));
+    assert_eq!(split("${a} ${missing}"), None);
+}
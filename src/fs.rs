@@ -1,38 +1,412 @@
 use std::{
     env,
-    fs::{read_to_string, OpenOptions},
-    io::Write,
+    ffi::{CString, OsString},
+    fs::{self, create_dir_all, read_to_string, File, OpenOptions},
+    io::{ErrorKind, LineWriter, Write},
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{symlink, OpenOptionsExt},
+    },
     path::{Path, PathBuf},
+    process::{self, Command},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
-use error_stack::{Result, ResultExt};
+use error_stack::{Report, Result, ResultExt};
 
 const HOME_VARIABLE: &str = "HOME";
+const CONFIG_VARIABLE: &str = "BACKER_CONFIG";
+const XDG_CONFIG_VARIABLE: &str = "XDG_CONFIG_HOME";
 const CONFIG_DIR: &str = ".config";
+const CONFIG_SUB_DIR: &str = "backer";
 const CONFIG_FILE_NAME: &str = "backer.toml";
+const SYSTEM_CONFIG: &str = "/etc/backer.toml";
+/// How many names to try for a temporary file before giving up
+const TEMP_ATTEMPTS: u32 = 100;
 
 mod error;
+mod mount;
+mod space;
 
 pub use error::SaveLogError;
+pub(crate) use error::{CheckError, LinkError, MountProblem, PathProblem, SpaceProblem};
+pub(crate) use mount::{check_mount, MountRequirement};
+pub(crate) use space::{check_space, format_size, free_space, parse_size, SpaceRequirement};
 
-pub(crate) fn read_config() -> Result<String, error::ReadConfigError> {
-    let home = env::var(HOME_VARIABLE).change_context(error::ReadConfigError)?;
-    let mut confpath = PathBuf::from(home);
-    confpath.push(CONFIG_DIR);
-    confpath.push(CONFIG_FILE_NAME);
-    Ok(read_to_string(&confpath).change_context(error::ReadConfigError)?)
+/// Find the config file to use.
+///
+/// An explicitly given path always wins and has to exist.
+/// Otherwise the first existing file out of
+/// `$BACKER_CONFIG`, `$XDG_CONFIG_HOME/backer/backer.toml`,
+/// `~/.config/backer.toml` and `/etc/backer.toml` is used.
+pub(crate) fn locate_config(explicit: Option<&Path>) -> Result<PathBuf, error::ReadConfigError> {
+    find_config(explicit, |name| env::var_os(name))
 }
 
-pub(crate) fn save(content: &str, path: &Path, append: bool) -> Result<(), error::SaveLogError> {
-    let mut file = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .append(append)
-        .open(path)
-        .change_context(error::SaveLogError)
-        .attach_printable_lazy(|| format!("Failed to open {:?}", path))?;
+/// [`locate_config`] with environment variables looked up by `var`
+fn find_config(
+    explicit: Option<&Path>,
+    var: impl Fn(&str) -> Option<OsString>,
+) -> Result<PathBuf, error::ReadConfigError> {
+    if let Some(path) = explicit {
+        let path = expand_tilde(path, var(HOME_VARIABLE));
+        return match path.is_file() {
+            true => Ok(path),
+            false => Err(Report::new(error::ReadConfigError)
+                .attach_printable(format!("Config file {:?} does not exist", path))),
+        };
+    }
 
-    writeln!(file, "{}", content)
-        .change_context(error::SaveLogError)
-        .attach_printable_lazy(|| format!("Failed to write to {:?}", file))
+    let candidates = config_candidates(var);
+    candidates
+        .iter()
+        .find(|path| path.is_file())
+        .cloned()
+        .ok_or_else(|| {
+            let tried = candidates
+                .iter()
+                .map(|path| format!("  {}", path.display()))
+                .collect::<Vec<_>>()
+                .join("\n");
+            Report::new(error::ReadConfigError)
+                .attach_printable(format!("No config file found. Tried:\n{}", tried))
+        })
 }
+
+/// The path a new config file should be created at.
+///
+/// Prefers `$XDG_CONFIG_HOME/backer/backer.toml` over `~/.config/backer.toml`.
+pub(crate) fn default_config_path() -> Result<PathBuf, error::ReadConfigError> {
+    if let Some(xdg) = env::var_os(XDG_CONFIG_VARIABLE).filter(|p| !p.is_empty()) {
+        return Ok(PathBuf::from_iter([
+            xdg.as_os_str(),
+            CONFIG_SUB_DIR.as_ref(),
+            CONFIG_FILE_NAME.as_ref(),
+        ]));
+    }
+    let home = env::var_os(HOME_VARIABLE)
+        .ok_or_else(|| Report::new(error::ReadConfigError))
+        .attach_printable("Neither $XDG_CONFIG_HOME nor $HOME is set")?;
+    Ok(PathBuf::from_iter([
+        home.as_os_str(),
+        CONFIG_DIR.as_ref(),
+        CONFIG_FILE_NAME.as_ref(),
+    ]))
+}
+
+pub(crate) fn read_config(path: &Path) -> Result<String, error::ReadConfigError> {
+    read_to_string(path)
+        .change_context(error::ReadConfigError)
+        .attach_printable_lazy(|| format!("Failed to read {:?}", path))
+}
+
+/// Write `content` to `path`, creating missing parent directories
+pub(crate) fn write_config(path: &Path, content: &str) -> Result<(), error::WriteConfigError> {
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent)
+            .change_context(error::WriteConfigError)
+            .attach_printable_lazy(|| format!("Failed to create {:?}", parent))?;
+    }
+    fs::write(path, content)
+        .change_context(error::WriteConfigError)
+        .attach_printable_lazy(|| format!("Failed to write {:?}", path))
+}
+
+/// Write `content` to a new file in the temporary directory,
+/// only readable and writable by the current user.
+///
+/// Files that already exist, like symlinks planted by other users, are never opened.
+pub(crate) fn write_temp_config(content: &str) -> Result<PathBuf, error::WriteConfigError> {
+    let dir = env::temp_dir();
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos());
+    for attempt in 0..TEMP_ATTEMPTS {
+        let name = format!("backer-{}-{:x}.toml", process::id(), nanos ^ attempt);
+        let path = dir.join(name);
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path);
+        let mut file = match file {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(Report::new(e)
+                    .change_context(error::WriteConfigError)
+                    .attach_printable(format!("Failed to create {:?}", path)))
+            }
+        };
+        file.write_all(content.as_bytes())
+            .change_context(error::WriteConfigError)
+            .attach_printable_lazy(|| format!("Failed to write {:?}", path))?;
+        return Ok(path);
+    }
+    Err(Report::new(error::WriteConfigError)
+        .attach_printable(format!("Failed to create a temporary file in {:?}", dir)))
+}
+
+/// A fresh directory for a test, removed again when dropped,
+/// even if the test fails
+#[cfg(test)]
+pub(crate) struct TempDir(PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let dir = env::temp_dir().join(format!("backer-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Replace a leading `~` with the current user's home directory.
+pub(crate) fn expand_home(path: &Path) -> PathBuf {
+    expand_tilde(path, env::var_os(HOME_VARIABLE))
+}
+
+fn expand_tilde(path: &Path, home: Option<OsString>) -> PathBuf {
+    let home = match home {
+        Some(home) => PathBuf::from(home),
+        None => return path.to_owned(),
+    };
+    match path.strip_prefix("~") {
+        Ok(rest) => home.join(rest),
+        Err(_) => path.to_owned(),
+    }
+}
+
+fn config_candidates(var: impl Fn(&str) -> Option<OsString>) -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+    if let Some(path) = var(CONFIG_VARIABLE).filter(|p| !p.is_empty()) {
+        candidates.push(expand_tilde(Path::new(&path), var(HOME_VARIABLE)));
+    }
+    if let Some(xdg) = var(XDG_CONFIG_VARIABLE).filter(|p| !p.is_empty()) {
+        candidates.push(PathBuf::from_iter([
+            xdg.as_os_str(),
+            CONFIG_SUB_DIR.as_ref(),
+            CONFIG_FILE_NAME.as_ref(),
+        ]));
+    }
+    if let Some(home) = var(HOME_VARIABLE) {
+        candidates.push(PathBuf::from_iter([
+            home.as_os_str(),
+            CONFIG_DIR.as_ref(),
+            CONFIG_FILE_NAME.as_ref(),
+        ]));
+    }
+    candidates.push(PathBuf::from(SYSTEM_CONFIG));
+    candidates
+}
+
+/// A log file written to line by line, so nothing is lost if backer is killed
+pub(crate) struct LogFile {
+    path: PathBuf,
+    writer: LineWriter<File>,
+}
+
+impl LogFile {
+    /// Open a log file, truncating it unless `append` is set
+    pub(crate) fn create(path: &Path, append: bool) -> Result<LogFile, error::SaveLogError> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)
+            .change_context(error::SaveLogError)
+            .attach_printable_lazy(|| format!("Failed to open {:?}", path))?;
+        Ok(LogFile {
+            path: path.to_owned(),
+            writer: LineWriter::new(file),
+        })
+    }
+
+    /// Write `text` as is, without adding a newline
+    pub(crate) fn write(&mut self, text: &str) -> Result<(), error::SaveLogError> {
+        self.writer
+            .write_all(text.as_bytes())
+            .change_context(error::SaveLogError)
+            .attach_printable_lazy(|| format!("Failed to write to {:?}", self.path))
+    }
+}
+
+/// Point the symlink `link` to `points_to`, replacing it atomically if it exists.
+///
+/// With `sudo`, `ln -sfn` is run through `sudo` instead, which isn't atomic.
+pub(crate) fn replace_symlink(
+    link: &Path,
+    points_to: &Path,
+    sudo: bool,
+) -> Result<(), error::LinkError> {
+    if sudo {
+        let status = Command::new("sudo")
+            .args(["ln", "-sfn", "--"])
+            .args([points_to, link])
+            .status()
+            .change_context(error::LinkError)
+            .attach_printable("Failed to run sudo")?;
+        return match status.success() {
+            true => Ok(()),
+            false => Err(Report::new(error::LinkError)
+                .attach_printable(format!("Failed to replace {:?}", link))
+                .attach_printable(format!("ln exited with {}", status))),
+        };
+    }
+
+    let mut temporary = link.as_os_str().to_owned();
+    temporary.push(format!(".{}.tmp", std::process::id()));
+    let temporary = PathBuf::from(temporary);
+
+    symlink(points_to, &temporary)
+        .change_context(error::LinkError)
+        .attach_printable_lazy(|| format!("Failed to create {:?}", temporary))?;
+    fs::rename(&temporary, link)
+        .change_context(error::LinkError)
+        .attach_printable_lazy(|| format!("Failed to replace {:?}", link))
+}
+
+// --- Pre-flight checks
+
+/// How a path a backup needs can be used
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum PathCheck {
+    Ok,
+    /// The directory is missing, but can be created
+    Create,
+    /// The path is on another host, so it can't be checked
+    Remote,
+}
+
+/// Check that a backup source exists and is readable.
+///
+/// Permissions aren't checked with `sudo`, as rsync runs as root then.
+pub(crate) fn check_source(path: &str, sudo: bool) -> Result<PathCheck, error::CheckError> {
+    if is_remote(path) {
+        return Ok(PathCheck::Remote);
+    }
+    let path = Path::new(path);
+    if !path.exists() {
+        return Err(path_problem(PathProblem::Missing, path));
+    }
+    let readable = match path.is_dir() {
+        true => fs::read_dir(path).is_ok(),
+        false => File::open(path).is_ok(),
+    };
+    match sudo || readable {
+        true => Ok(PathCheck::Ok),
+        false => Err(path_problem(PathProblem::Unreadable, path)),
+    }
+}
+
+/// Check that rsync can write to a backup target.
+///
+/// rsync creates a missing target itself as long as its parent exists,
+/// missing parents are only accepted if `create` is set.
+pub(crate) fn check_target(
+    path: &str,
+    create: bool,
+    sudo: bool,
+) -> Result<PathCheck, error::CheckError> {
+    if is_remote(path) {
+        return Ok(PathCheck::Remote);
+    }
+    let path = Path::new(path);
+    match parent_dir(path) {
+        parent if !path.exists() && parent.exists() => check_writable(parent, false, sudo),
+        _ => check_writable(path, create, sudo),
+    }
+}
+
+/// Check that the directory a log file is saved in exists or can be created
+pub(crate) fn check_log(path: &str) -> Result<PathCheck, error::CheckError> {
+    check_writable(parent_dir(Path::new(path)), true, false)
+}
+
+/// Create a directory that was checked as [`PathCheck::Create`]
+pub(crate) fn create_dir(path: &Path) -> Result<(), error::CheckError> {
+    create_dir_all(path)
+        .change_context(error::CheckError)
+        .attach_printable_lazy(|| format!("Failed to create {:?}", path))
+}
+
+/// The directory containing `path`, `.` for bare file names
+pub(crate) fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// Check that `path` is writable or,
+/// if it's missing and `create` is set, that its closest existing ancestor is.
+fn check_writable(path: &Path, create: bool, sudo: bool) -> Result<PathCheck, error::CheckError> {
+    let existing = match path.exists() {
+        true => path,
+        false if create => path
+            .ancestors()
+            .map(|p| {
+                if p.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    p
+                }
+            })
+            .find(|p| p.exists())
+            .unwrap_or(Path::new("/")),
+        false => return Err(path_problem(PathProblem::Missing, path)),
+    };
+    if !sudo && !is_writable(existing) {
+        return Err(path_problem(PathProblem::Unwritable, existing));
+    }
+    match existing == path {
+        true => Ok(PathCheck::Ok),
+        false => Ok(PathCheck::Create),
+    }
+}
+
+fn is_writable(path: &Path) -> bool {
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: `path` is a valid NUL-terminated string, that outlives the call
+    unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+}
+
+/// Whether rsync treats `path` as remote, like `host:dir` or `rsync://host/dir`
+pub(crate) fn is_remote(path: &str) -> bool {
+    path.split('/')
+        .next()
+        .is_some_and(|first| first.contains(':'))
+}
+
+fn path_problem(problem: PathProblem, path: &Path) -> Report<error::CheckError> {
+    Report::new(error::CheckError)
+        .attach_printable(problem)
+        .attach_printable(format!("Path: {:?}", path))
+}
+
+#[cfg(test)]
+mod tests;
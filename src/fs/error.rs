@@ -9,6 +9,15 @@ impl Display for ReadConfigError {
     }
 }
 
+#[derive(Debug)]
+pub(crate) struct WriteConfigError;
+impl Error for WriteConfigError {}
+impl Display for WriteConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Failed to write configuration")
+    }
+}
+
 #[derive(Debug)]
 pub struct SaveLogError;
 impl Error for SaveLogError {}
@@ -18,6 +27,15 @@ impl Display for SaveLogError {
     }
 }
 
+#[derive(Debug)]
+pub(crate) struct LinkError;
+impl Error for LinkError {}
+impl Display for LinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Failed to update link")
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct CheckError;
 impl Error for CheckError {}
@@ -26,3 +44,50 @@ impl Display for CheckError {
         write!(f, "File check failed")
     }
 }
+
+/// Why a path failed its check, attached to [`CheckError`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum PathProblem {
+    Missing,
+    Unreadable,
+    Unwritable,
+}
+impl Display for PathProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathProblem::Missing => write!(f, "Path does not exist"),
+            PathProblem::Unreadable => write!(f, "Path is not readable"),
+            PathProblem::Unwritable => write!(f, "Path is not writable"),
+        }
+    }
+}
+
+/// Why a target failed its mount check, attached to [`CheckError`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum MountProblem {
+    NotMounted,
+    /// No drive with this UUID or label is connected
+    NoDrive(String),
+    /// The target isn't on the drive with this UUID or label
+    WrongDrive(String),
+}
+impl Display for MountProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MountProblem::NotMounted => write!(f, "Target is not on a mounted drive"),
+            MountProblem::NoDrive(drive) => write!(f, "No drive with {} is connected", drive),
+            MountProblem::WrongDrive(drive) => {
+                write!(f, "Target is not on the drive with {}", drive)
+            }
+        }
+    }
+}
+
+/// Why a target failed its free space check, attached to [`CheckError`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct SpaceProblem(pub String);
+impl Display for SpaceProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
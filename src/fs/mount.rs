@@ -0,0 +1,152 @@
+use std::{
+    fs::{canonicalize, read_to_string},
+    path::{Path, PathBuf},
+};
+
+use error_stack::{Report, Result, ResultExt};
+
+use super::{
+    error::{CheckError, MountProblem},
+    is_remote,
+};
+
+const MOUNTINFO: &str = "/proc/self/mountinfo";
+const BY_UUID: &str = "/dev/disk/by-uuid";
+const BY_LABEL: &str = "/dev/disk/by-label";
+
+/// Filesystems without a drive behind them, which a backup is never meant to end up on
+const VIRTUAL_FILESYSTEMS: &[&str] = &["tmpfs", "ramfs", "devtmpfs", "proc", "sysfs"];
+
+/// What a backup target has to be mounted on
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct MountRequirement {
+    /// Any drive other than the root filesystem
+    pub mounted: bool,
+    pub uuid: Option<String>,
+    pub label: Option<String>,
+}
+
+impl MountRequirement {
+    pub fn is_empty(&self) -> bool {
+        !self.mounted && self.uuid.is_none() && self.label.is_none()
+    }
+}
+
+struct Mount {
+    point: PathBuf,
+    fs_type: String,
+    source: String,
+}
+
+/// Check that `target` is on a mounted drive as required.
+///
+/// Remote targets are not checked.
+pub(crate) fn check_mount(target: &str, required: &MountRequirement) -> Result<(), CheckError> {
+    if required.is_empty() || is_remote(target) {
+        return Ok(());
+    }
+
+    let mount = containing_mount(Path::new(target))?;
+    let drives = [
+        required.uuid.as_ref().map(|u| (BY_UUID, u, "UUID")),
+        required.label.as_ref().map(|l| (BY_LABEL, l, "label")),
+    ];
+    for (dir, id, kind) in drives.into_iter().flatten() {
+        let drive = format!("{} {:?}", kind, id);
+        let device = canonicalize(Path::new(dir).join(escape_udev(id))).map_err(|_| {
+            Report::new(CheckError).attach_printable(MountProblem::NoDrive(drive.clone()))
+        })?;
+        if canonicalize(&mount.source).ok() != Some(device) {
+            return Err(Report::new(CheckError)
+                .attach_printable(MountProblem::WrongDrive(drive))
+                .attach_printable(format!(
+                    "Target is on {} mounted at {:?}",
+                    mount.source, mount.point
+                )));
+        }
+    }
+
+    if mount.point == Path::new("/") || VIRTUAL_FILESYSTEMS.contains(&mount.fs_type.as_str()) {
+        return Err(Report::new(CheckError)
+            .attach_printable(MountProblem::NotMounted)
+            .attach_printable(format!(
+                "Target would end up on {} ({}) mounted at {:?}",
+                mount.source, mount.fs_type, mount.point
+            )));
+    }
+    Ok(())
+}
+
+/// The mount the closest existing ancestor of `path` is on
+fn containing_mount(path: &Path) -> Result<Mount, CheckError> {
+    let existing = path
+        .ancestors()
+        .find_map(|p| canonicalize(p).ok())
+        .unwrap_or_else(|| PathBuf::from("/"));
+
+    let mountinfo = read_to_string(MOUNTINFO)
+        .change_context(CheckError)
+        .attach_printable_lazy(|| format!("Failed to read {}", MOUNTINFO))?;
+
+    // Later mounts hide earlier ones on the same mount point,
+    // so the last of the longest matches is the one that counts
+    mountinfo
+        .lines()
+        .filter_map(parse_mount)
+        .filter(|m| existing.starts_with(&m.point))
+        .max_by_key(|m| m.point.components().count())
+        .ok_or_else(|| {
+            Report::new(CheckError).attach_printable(format!("No mount found for {:?}", existing))
+        })
+}
+
+/// Parse a line of `/proc/self/mountinfo`, like
+/// `36 35 98:0 / /mnt rw,noatime master:1 - ext3 /dev/root rw,errors=continue`
+fn parse_mount(line: &str) -> Option<Mount> {
+    let (mount, filesystem) = line.split_once(" - ")?;
+    let point = mount.split(' ').nth(4)?;
+    let mut filesystem = filesystem.split(' ');
+    Some(Mount {
+        point: PathBuf::from(unescape_octal(point)),
+        fs_type: filesystem.next()?.to_owned(),
+        source: unescape_octal(filesystem.next()?),
+    })
+}
+
+/// Undo the escaping of spaces and such in mountinfo, like `\040` for ` `
+fn unescape_octal(s: &str) -> String {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        let code = tail
+            .get(..3)
+            .and_then(|d| std::str::from_utf8(d).ok())
+            .and_then(|d| u8::from_str_radix(d, 8).ok());
+        match (b, code) {
+            (b'\\', Some(code)) => {
+                bytes.push(code);
+                rest = &tail[3..];
+            }
+            _ => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Escape a UUID or label the way udev names its links, like `\x20` for ` `
+fn escape_udev(s: &str) -> String {
+    s.bytes()
+        .map(
+            |b| match b.is_ascii_alphanumeric() || b"#+-.:=@_".contains(&b) {
+                true => (b as char).to_string(),
+                false => format!("\\x{:02x}", b),
+            },
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,21 @@
+use super::*;
+
+#[test]
+fn parsing_mounts() {
+    let mount = parse_mount(r"36 35 98:0 / /run/media/my\040drive rw - ext4 /dev/sdb1 rw").unwrap();
+    assert_eq!(mount.point, Path::new("/run/media/my drive"));
+    assert_eq!(mount.fs_type, "ext4");
+    assert_eq!(mount.source, "/dev/sdb1");
+
+    let mount = parse_mount("36 35 98:0 / /mnt rw,noatime master:1 - ext3 /dev/root rw").unwrap();
+    assert_eq!(mount.point, Path::new("/mnt"));
+    assert_eq!(mount.fs_type, "ext3");
+
+    assert!(parse_mount("not a mount").is_none());
+}
+
+#[test]
+fn escaping_labels() {
+    assert_eq!(escape_udev("1234-ABCD"), "1234-ABCD");
+    assert_eq!(escape_udev("My Drive/2"), r"My\x20Drive\x2f2");
+}
@@ -0,0 +1,176 @@
+use std::{ffi::CString, mem::MaybeUninit, os::unix::ffi::OsStrExt, path::Path};
+
+use error_stack::{Report, Result};
+
+use super::{
+    error::{CheckError, SpaceProblem},
+    is_remote,
+};
+
+const UNITS: &[(&str, u64)] = &[
+    ("b", 1),
+    ("k", 1 << 10),
+    ("kb", 1000),
+    ("kib", 1 << 10),
+    ("m", 1 << 20),
+    ("mb", 1000 * 1000),
+    ("mib", 1 << 20),
+    ("g", 1 << 30),
+    ("gb", 1000 * 1000 * 1000),
+    ("gib", 1 << 30),
+    ("t", 1 << 40),
+    ("tb", 1000 * 1000 * 1000 * 1000),
+    ("tib", 1 << 40),
+];
+
+/// How much space has to be left on the target after a backup
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct SpaceRequirement {
+    /// In bytes
+    pub min_free: Option<u64>,
+    pub min_free_percent: Option<f64>,
+    /// Estimate the size of the transfer with a dry run first
+    pub estimate: bool,
+}
+
+impl SpaceRequirement {
+    pub fn is_empty(&self) -> bool {
+        self.min_free.is_none() && self.min_free_percent.is_none() && !self.estimate
+    }
+}
+
+/// Space on a filesystem, in bytes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Space {
+    /// Available to unprivileged users
+    pub free: u64,
+    pub total: u64,
+}
+
+/// Space on the filesystem `path`, or its closest existing ancestor, is on
+pub(crate) fn free_space(path: &Path) -> Result<Space, CheckError> {
+    let existing = path
+        .ancestors()
+        .map(|p| {
+            if p.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                p
+            }
+        })
+        .find(|p| p.exists())
+        .unwrap_or(Path::new("/"));
+    let c_path = CString::new(existing.as_os_str().as_bytes())
+        .map_err(|e| Report::new(e).change_context(CheckError))?;
+
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `c_path` is a valid NUL-terminated string and `stat` is only read on success
+    let stat = match unsafe { libc::statvfs(c_path.as_ptr(), stat.as_mut_ptr()) } {
+        0 => unsafe { stat.assume_init() },
+        _ => {
+            return Err(Report::new(std::io::Error::last_os_error())
+                .change_context(CheckError)
+                .attach_printable(format!("Failed to query free space of {:?}", existing)))
+        }
+    };
+
+    let fragment = stat.f_frsize as u64;
+    Ok(Space {
+        free: stat.f_bavail as u64 * fragment,
+        total: stat.f_blocks as u64 * fragment,
+    })
+}
+
+/// Check that enough space is left on the target after transferring `transfer` bytes.
+///
+/// Remote targets are not checked.
+pub(crate) fn check_space(
+    target: &str,
+    required: &SpaceRequirement,
+    transfer: Option<u64>,
+) -> Result<(), CheckError> {
+    if required.is_empty() || is_remote(target) {
+        return Ok(());
+    }
+
+    let space = free_space(Path::new(target))?;
+    check_space_left(space, required, transfer.unwrap_or_default())
+}
+
+/// Check that enough of `space` is left after transferring `transfer` bytes
+fn check_space_left(
+    space: Space,
+    required: &SpaceRequirement,
+    transfer: u64,
+) -> Result<(), CheckError> {
+    let left = space.free.saturating_sub(transfer);
+    let problem = |problem: String| {
+        Err(Report::new(CheckError)
+            .attach_printable(SpaceProblem(problem))
+            .attach_printable(format!(
+                "{} free out of {}, transfer is estimated at {}",
+                format_size(space.free),
+                format_size(space.total),
+                format_size(transfer)
+            )))
+    };
+
+    if transfer > space.free {
+        return problem(format!(
+            "Transfer of {} doesn't fit into {} free on target",
+            format_size(transfer),
+            format_size(space.free)
+        ));
+    }
+    if let Some(min_free) = required.min_free.filter(|min| left < *min) {
+        return problem(format!(
+            "Only {} would be left on target, {} required",
+            format_size(left),
+            format_size(min_free)
+        ));
+    }
+    let percent = match space.total {
+        0 => 0.0,
+        total => left as f64 * 100.0 / total as f64,
+    };
+    if let Some(min_percent) = required.min_free_percent.filter(|min| percent < *min) {
+        return problem(format!(
+            "Only {:.1}% would be left on target, {}% required",
+            percent, min_percent
+        ));
+    }
+    Ok(())
+}
+
+/// Parse a size like `20GiB`, `1.5 TB` or `512`, in bytes
+pub(crate) fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number = number.parse::<f64>().ok()?;
+    let unit = match unit.trim().to_lowercase() {
+        unit if unit.is_empty() => 1,
+        unit => UNITS.iter().find(|(u, _)| *u == unit)?.1,
+    };
+    Some((number * unit as f64) as u64)
+}
+
+/// Format a size in bytes for humans, like `20.0 GiB`
+pub(crate) fn format_size(bytes: u64) -> String {
+    const BINARY: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < BINARY.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", size, BINARY[unit]),
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,72 @@
+use super::*;
+
+#[test]
+fn parsing_sizes() {
+    assert_eq!(parse_size("512"), Some(512));
+    assert_eq!(parse_size("20GiB"), Some(20 << 30));
+    assert_eq!(parse_size("1.5 TB"), Some(1_500_000_000_000));
+    assert_eq!(parse_size("2k"), Some(2048));
+    assert_eq!(parse_size("20 parsecs"), None);
+    assert_eq!(parse_size("GiB"), None);
+}
+
+#[test]
+fn formatting_sizes() {
+    assert_eq!(format_size(512), "512 B");
+    assert_eq!(format_size(20 << 30), "20.0 GiB");
+    assert_eq!(format_size(1536), "1.5 KiB");
+}
+
+#[test]
+fn checking_space_left() {
+    const GIB: u64 = 1 << 30;
+    let space = Space {
+        free: 10 * GIB,
+        total: 100 * GIB,
+    };
+    let problem = |space: Space, required: &SpaceRequirement, transfer: u64| {
+        check_space_left(space, required, transfer)
+            .err()
+            .map(|e| e.downcast_ref::<SpaceProblem>().unwrap().0.clone())
+    };
+
+    let min_free = SpaceRequirement {
+        min_free: Some(5 * GIB),
+        ..Default::default()
+    };
+    assert_eq!(problem(space, &min_free, 0), None);
+    assert_eq!(problem(space, &min_free, 5 * GIB), None);
+    assert_eq!(
+        problem(space, &min_free, 6 * GIB).as_deref(),
+        Some("Only 4.0 GiB would be left on target, 5.0 GiB required")
+    );
+    assert_eq!(
+        problem(space, &min_free, 11 * GIB).as_deref(),
+        Some("Transfer of 11.0 GiB doesn't fit into 10.0 GiB free on target")
+    );
+
+    let min_free_percent = SpaceRequirement {
+        min_free_percent: Some(8.0),
+        ..Default::default()
+    };
+    assert_eq!(problem(space, &min_free_percent, 2 * GIB), None);
+    assert_eq!(
+        problem(space, &min_free_percent, 3 * GIB).as_deref(),
+        Some("Only 7.0% would be left on target, 8% required")
+    );
+
+    // A second backup to the same filesystem sees what the first one left
+    let shared = SpaceRequirement {
+        min_free: Some(4 * GIB),
+        ..Default::default()
+    };
+    assert_eq!(problem(space, &shared, 4 * GIB), None);
+    let left = Space {
+        free: space.free - 4 * GIB,
+        ..space
+    };
+    assert!(problem(left, &shared, 4 * GIB).is_some());
+
+    let empty = Space { free: 0, total: 0 };
+    assert!(problem(empty, &min_free_percent, 0).is_some());
+}
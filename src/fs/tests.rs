@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+
+use super::*;
+
+#[test]
+fn locating_config() {
+    let dir = TempDir::new("locate");
+    let home = dir.join("home");
+    let xdg = dir.join("xdg");
+    let explicit = home.join("explicit.toml");
+    let from_env = home.join("env.toml");
+    let from_xdg = xdg.join("backer/backer.toml");
+    let from_home = home.join(".config/backer.toml");
+    for path in [&explicit, &from_env, &from_xdg, &from_home] {
+        write_config(path, "").unwrap();
+    }
+
+    let mut vars = HashMap::from([
+        (HOME_VARIABLE, home.clone().into_os_string()),
+        (CONFIG_VARIABLE, OsString::from("~/env.toml")),
+        (XDG_CONFIG_VARIABLE, xdg.clone().into_os_string()),
+    ]);
+    let locate = |explicit: Option<&str>, vars: &HashMap<&str, OsString>| {
+        find_config(explicit.map(Path::new), |name| vars.get(name).cloned())
+    };
+
+    assert_eq!(locate(Some("~/explicit.toml"), &vars).unwrap(), explicit);
+    assert!(locate(Some("~/missing.toml"), &vars).is_err());
+    assert_eq!(locate(None, &vars).unwrap(), from_env);
+
+    // Candidates that don't exist are skipped
+    vars.insert(CONFIG_VARIABLE, OsString::from("~/missing.toml"));
+    assert_eq!(locate(None, &vars).unwrap(), from_xdg);
+    vars.insert(XDG_CONFIG_VARIABLE, OsString::new());
+    assert_eq!(locate(None, &vars).unwrap(), from_home);
+
+    fs::remove_file(&from_home).unwrap();
+    let tried = format!(
+        "No config file found. Tried:\n  {}\n  {}\n  {}",
+        home.join("missing.toml").display(),
+        from_home.display(),
+        SYSTEM_CONFIG
+    );
+    match locate(None, &vars) {
+        Ok(path) => assert_eq!(path, Path::new(SYSTEM_CONFIG)),
+        Err(e) => assert!(e
+            .frames()
+            .filter_map(|frame| frame.downcast_ref::<String>())
+            .any(|s| *s == tried)),
+    }
+}
@@ -1,21 +1,35 @@
 use std::{
+    env,
     error::Error,
     fmt,
+    fs::remove_file,
+    path::Path,
     process::{exit, Command},
+    ptr,
     str::FromStr,
 };
 
 use clap::Parser;
-use config::Config;
-use error_stack::{Result, ResultExt};
-use fs::read_config;
+use config::{
+    run_in_order, validate_backups, Backup, Config, Restore, Selection, Settings, UnknownKey,
+};
+use error_stack::{Report, Result, ResultExt};
+use fs::{
+    default_config_path, expand_home, format_size, locate_config, read_config, write_config,
+    write_temp_config, CheckError, MountProblem, SpaceProblem,
+};
 use opt::Opt;
+use rsync::{describe_status, Change, Outcome, TransferStats};
+use runner::CommandOutput;
+use term::{prompt, wait_for_continue, Color};
 
 mod config;
 mod ctx_string;
 mod fs;
 mod opt;
+mod rsync;
 mod runner;
+mod term;
 
 #[derive(Debug)]
 struct FatalError;
@@ -26,79 +40,652 @@ impl fmt::Display for FatalError {
     }
 }
 
+const DEFAULT_CONFIG: &str = include_str!("default.toml");
+const DEFAULT_EDITOR: &str = "vi";
+const VISUAL_VARIABLE: &str = "VISUAL";
+const EDITOR_VARIABLE: &str = "EDITOR";
+
+/// Every backup ran successfully
+const EXIT_OK: i32 = 0;
+/// At least one backup failed or was skipped
+const EXIT_FAILED: i32 = 1;
+/// Backer couldn't start, e.g. because of a config error
+const EXIT_FATAL: i32 = 2;
+
 fn main() {
     let opt = Opt::parse();
-    check_rsync_exists();
 
-    if let Err(e) = match opt.command {
-        opt::Command::Run => run(),
-        opt::Command::Preview => preview(),
-        opt::Command::Configure { editor } => todo!(),
-    } {
-        eprintln!("{e:?}");
-        exit(1);
+    let result = match opt.command {
+        opt::Command::Run { select } => {
+            check_rsync_exists();
+            run(opt.config.as_deref(), &selection(select))
+        }
+        opt::Command::List { select } => list(opt.config.as_deref(), &selection(select)),
+        opt::Command::Preview => {
+            check_rsync_exists();
+            preview(opt.config.as_deref()).map(|_| EXIT_OK)
+        }
+        opt::Command::Verify { select } => {
+            check_rsync_exists();
+            verify(opt.config.as_deref(), &selection(select))
+        }
+        opt::Command::Prune { select, dry_run } => {
+            prune(opt.config.as_deref(), &selection(select), dry_run)
+        }
+        opt::Command::Restore {
+            name,
+            snapshot,
+            to,
+            path,
+            delete,
+            yes,
+        } => {
+            check_rsync_exists();
+            let restore = Restore {
+                snapshot,
+                to,
+                path,
+                delete,
+            };
+            self::restore(opt.config.as_deref(), name, &restore, yes)
+        }
+        opt::Command::Check => check(opt.config.as_deref()),
+        opt::Command::Configure { editor } => configure(opt.config.as_deref(), editor),
+    };
+
+    match result {
+        Ok(code) => exit(code),
+        Err(e) => {
+            eprintln!("{e:?}");
+            exit(EXIT_FATAL);
+        }
     }
 }
 
-fn run() -> Result<(), FatalError> {
-    let config = read_config().change_context(FatalError)?;
-    let (shared_context, runners) = Config::from_str(&config)
-        .change_context(FatalError)?
+fn run(config_path: Option<&Path>, selection: &Selection) -> Result<i32, FatalError> {
+    let (settings, shared_context, runners) = load_config(config_path)?
         .build()
         .change_context(FatalError)?;
+    let style = settings.run.style();
+    let runners = selection.apply(&runners).change_context(FatalError)?;
+
+    let runs = run_in_order(
+        &runners,
+        |runner| runner.on_failure(),
+        |i, runner| {
+            let num = runner.label(&format!("{}/{}", i + 1, runners.len()));
+            println!("\n{}\n", style.header(&format!("BACKUP {}", num)));
+            // Checked right before running, after earlier backups changed the disks
+            let result = match runner.preflight(&shared_context) {
+                Ok(()) => Ok(runner.run(&shared_context)),
+                Err(e) => {
+                    eprintln!("Pre-flight checks failed, skipping backup\n{:?}", e);
+                    Err(skip_reason(&e))
+                }
+            };
+            println!("{}", style.rule());
+            let failed = result.as_ref().map_or(true, |r| is_failure(runner, r));
+            ((num, result), failed)
+        },
+    );
+
+    let ok = style.paint(Outcome::Ok, Color::Green);
+    let warn = style.paint(Outcome::Warn, Color::Yellow);
+    let fail = style.paint(Outcome::Fail, Color::Red);
+    let skip = style.paint("SKIPPED", Color::Red);
 
-    let runners = Vec::from_iter(runners.iter().enumerate().map(|(i, runner)| {
-        let num = format!("{}/{}", i + 1, runners.len());
-        println!("\n[BACKUP {}]\n", num);
-        (num, runner.run(&shared_context))
-    }));
+    if runs.aborted {
+        if let Some((num, result)) = runs.results.last() {
+            match result {
+                Err(reason) => eprintln!("Backup {} : {}\n* {}", num, skip, reason),
+                Ok(Ok(CommandOutput {
+                    log_result: Err(e), ..
+                })) => eprintln!("Log {} : {}\n{:?}", num, fail, e),
+                Ok(Ok(CommandOutput {
+                    link_result: Err(e),
+                    ..
+                })) => eprintln!("Link {} : {}\n{:?}", num, fail, e),
+                Ok(Ok(CommandOutput { status, .. })) => {
+                    eprintln!("Backup {} : {}\n* {}", num, fail, describe_status(status))
+                }
+                Ok(Err(e)) => eprintln!("Backup {} : {}\n{:?}", num, fail, e),
+            }
+        }
+        eprintln!("\nAborting remaining backups");
+        return Ok(EXIT_FAILED);
+    }
 
-    println!("\n\n\n[SUMMARY]\n");
-    for (num, result) in runners {
+    println!("\n\n\n{}\n", style.header("SUMMARY"));
+    let succeeded = runs.succeeded();
+    for ((num, result), runner) in runs.results.into_iter().zip(&runners) {
+        let result = match result {
+            Ok(result) => result,
+            Err(reason) => {
+                println!("Backup {} : {}\n* {}\n", num, skip, reason);
+                continue;
+            }
+        };
         match result {
-            Ok(((status, out, err), log_result)) => {
+            Ok(output) => {
+                let details = match &output.stats {
+                    Some(stats) => describe_stats(stats),
+                    None => format!(
+                        "* {} lines on stdout\n* {} lines on stderr",
+                        output.stdout_lines, output.stderr_lines
+                    ),
+                };
+                let details = match &output.changes {
+                    Some(counts) => format!("{}\n* {}", details, counts),
+                    None => details,
+                };
                 println!(
-                    "Backup {} : OK\n\
-                    * {}\n\
-                    * {} lines on stdout\n\
-                    * {} lines on stderr\n",
+                    "Backup {} : {}\n* {}\n{}\n",
                     num,
-                    status,
-                    out.lines().count(),
-                    err.lines().count()
+                    match runner.classify(&output.status) {
+                        Outcome::Ok => &ok,
+                        Outcome::Warn => &warn,
+                        Outcome::Fail => &fail,
+                    },
+                    describe_status(&output.status),
+                    details
                 );
 
-                match log_result {
-                    Ok(_) => println!("Log {} : OK\n", num),
-                    Err(e) => eprintln!("Log {} : FAIL\n{:?}\n", num, e),
+                match output.log_result {
+                    Ok(_) => println!("Log {} : {}\n", num, ok),
+                    Err(e) => eprintln!("Log {} : {}\n{:?}\n", num, fail, e),
+                }
+                if let Err(e) = output.link_result {
+                    eprintln!("Link {} : {}\n{:?}\n", num, fail, e);
                 }
             }
-            Err(e) => eprintln!("Backup {} : FAIL\n{:?}\n", num, e),
+            Err(e) => eprintln!("Backup {} : {}\n{:?}\n", num, fail, e),
         }
     }
+    for (i, runner) in runners
+        .iter()
+        .enumerate()
+        .skip(runners.len() - runs.skipped)
+    {
+        let num = runner.label(&format!("{}/{}", i + 1, runners.len()));
+        println!("Backup {} : {}\n", num, skip);
+    }
 
-    Ok(())
+    Ok(match succeeded {
+        true => EXIT_OK,
+        false => EXIT_FAILED,
+    })
 }
 
-fn preview() -> Result<(), FatalError> {
-    let config = read_config().change_context(FatalError)?;
-    let (shared_context, runners) = Config::from_str(&config)
-        .change_context(FatalError)?
+fn list(config_path: Option<&Path>, selection: &Selection) -> Result<i32, FatalError> {
+    let (settings, _, runners) = load_config(config_path)?
         .build()
         .change_context(FatalError)?;
+    let style = settings.run.style();
+    let selected = selection.apply(&runners).change_context(FatalError)?;
 
+    // Numbered by position in the config, so the numbers stay stable when filtering
     for (i, runner) in runners.iter().enumerate() {
-        let name = format!("{}/{}", i + 1, runners.len());
-        runner.preview(&shared_context, name);
+        if !selected.iter().any(|s| ptr::eq(*s, runner)) {
+            continue;
+        }
+        let mut line = style.paint(
+            runner.label(&format!("{}/{}", i + 1, runners.len())),
+            Color::Bold,
+        );
+        if !runner.tags().is_empty() {
+            line.push_str(&format!(" [{}]", runner.tags().join(", ")));
+        }
+        if let Some(description) = runner.description() {
+            line.push_str(&format!("\n    {}", description));
+        }
+        println!("{}", line);
     }
 
+    Ok(EXIT_OK)
+}
+
+/// Compare each backup with its source by checksum
+fn verify(config_path: Option<&Path>, selection: &Selection) -> Result<i32, FatalError> {
+    let (settings, shared_context, runners) = load_config(config_path)?
+        .build()
+        .change_context(FatalError)?;
+    let style = settings.run.style();
+    let runners = selection.apply(&runners).change_context(FatalError)?;
+
+    let mut results = Vec::with_capacity(runners.len());
+    for (i, runner) in runners.iter().enumerate() {
+        let num = runner.label(&format!("{}/{}", i + 1, runners.len()));
+        println!("\n{}\n", style.header(&format!("VERIFY {}", num)));
+        let items = match runner.verify(&shared_context) {
+            Ok(items) => items,
+            Err(e) => {
+                eprintln!("{e:?}");
+                results.push((num, None));
+                continue;
+            }
+        };
+        for item in &items {
+            let (label, color) = match item.change {
+                Change::Created => ("MISSING", Color::Red),
+                Change::Deleted => ("EXTRA  ", Color::Yellow),
+                _ => ("DIFFERS", Color::Red),
+            };
+            println!("{} {}", style.paint(label, color), item.path);
+        }
+        if items.is_empty() {
+            println!("No differences found");
+        }
+        results.push((num, Some(items.len())));
+    }
+
+    let ok = style.paint(Outcome::Ok, Color::Green);
+    let fail = style.paint(Outcome::Fail, Color::Red);
+    println!("\n\n\n{}\n", style.header("SUMMARY"));
+    for (num, result) in &results {
+        match result {
+            Some(0) => println!("Backup {} : {}\n* Target matches the source\n", num, ok),
+            Some(n) => println!("Backup {} : {}\n* {} difference(s)\n", num, fail, n),
+            None => println!("Backup {} : {}\n* Couldn't verify\n", num, fail),
+        }
+    }
+
+    Ok(match results.iter().all(|(_, r)| *r == Some(0)) {
+        true => EXIT_OK,
+        false => EXIT_FAILED,
+    })
+}
+
+/// Delete the snapshots of each backup its retention policy doesn't keep
+fn prune(
+    config_path: Option<&Path>,
+    selection: &Selection,
+    dry_run: bool,
+) -> Result<i32, FatalError> {
+    let (settings, shared_context, runners) = load_config(config_path)?
+        .build()
+        .change_context(FatalError)?;
+    let style = settings.run.style();
+    let selected = selection.apply(&runners).change_context(FatalError)?;
+
+    let mut failed = false;
+    let mut deleted = 0;
+    for (i, runner) in runners.iter().enumerate() {
+        if !selected.iter().any(|s| ptr::eq(*s, runner)) {
+            continue;
+        }
+        let num = runner.label(&format!("{}/{}", i + 1, runners.len()));
+        println!("\n{}\n", style.header(&format!("PRUNE {}", num)));
+        if !runner.prunes() {
+            println!("No snapshots with a retention policy, nothing to prune");
+            continue;
+        }
+        let snapshots = match runner.snapshots(&shared_context) {
+            Ok(snapshots) => snapshots,
+            Err(e) => {
+                eprintln!("{e:?}");
+                failed = true;
+                continue;
+            }
+        };
+        if snapshots.is_empty() {
+            println!("No snapshots found");
+        }
+        for snapshot in &snapshots {
+            let path = snapshot.path.display();
+            if snapshot.keep {
+                let latest = if snapshot.latest { " (latest)" } else { "" };
+                println!("{} {}{}", style.paint("KEEP  ", Color::Green), path, latest);
+                continue;
+            }
+            println!("{} {}", style.paint("DELETE", Color::Red), path);
+            if dry_run {
+                deleted += 1;
+                continue;
+            }
+            match runner.remove_snapshot(snapshot) {
+                Ok(()) => deleted += 1,
+                Err(e) => {
+                    eprintln!("{e:?}");
+                    failed = true;
+                }
+            }
+        }
+    }
+
+    match dry_run {
+        true => println!("\n{} snapshot(s) would be deleted", deleted),
+        false => println!("\n{} snapshot(s) deleted", deleted),
+    }
+    Ok(match failed {
+        true => EXIT_FAILED,
+        false => EXIT_OK,
+    })
+}
+
+/// Copy a backup back, after showing a dry run and asking for confirmation
+fn restore(
+    config_path: Option<&Path>,
+    name: String,
+    restore: &Restore,
+    yes: bool,
+) -> Result<i32, FatalError> {
+    let (settings, shared_context, runners) = load_config(config_path)?
+        .build()
+        .change_context(FatalError)?;
+    let style = settings.run.style();
+    let selection = Selection {
+        only: vec![name],
+        ..Default::default()
+    };
+    let runner = selection.apply(&runners).change_context(FatalError)?[0];
+    let command = runner
+        .restore(&shared_context, restore)
+        .change_context(FatalError)?;
+
+    println!("Restoring {} to {}", command.from, command.to);
+    if restore.delete {
+        println!(
+            "{}: Files missing from the backup will be deleted",
+            style.paint(Outcome::Warn, Color::Yellow)
+        );
+    }
+    if !yes {
+        println!("\n{}\n", style.header("DRY RUN"));
+        let status = command
+            .command(true)
+            .status()
+            .change_context(FatalError)
+            .attach_printable("Failed to run rsync")?;
+        println!("{}", style.rule());
+        if runner.classify(&status) == Outcome::Fail {
+            eprintln!("Dry run failed: {}", describe_status(&status));
+            return Ok(EXIT_FAILED);
+        }
+        match prompt("Restore these files? [y]es or [n]o") {
+            Some(a) if a.eq_ignore_ascii_case("y") => println!(),
+            _ => {
+                println!("Nothing was restored");
+                return Ok(EXIT_FAILED);
+            }
+        }
+    }
+
+    let status = command
+        .command(false)
+        .status()
+        .change_context(FatalError)
+        .attach_printable("Failed to run rsync")?;
+    let outcome = runner.classify(&status);
+    let color = match outcome {
+        Outcome::Ok => Color::Green,
+        Outcome::Warn => Color::Yellow,
+        Outcome::Fail => Color::Red,
+    };
+    println!(
+        "\nRestore : {}\n* {}",
+        style.paint(outcome, color),
+        describe_status(&status)
+    );
+    Ok(match outcome {
+        Outcome::Fail => EXIT_FAILED,
+        _ => EXIT_OK,
+    })
+}
+
+/// Summary of rsync's transfer statistics
+fn describe_stats(stats: &TransferStats) -> String {
+    format!(
+        "* {} files transferred, {} created, {} deleted\n\
+        * {} of {} transferred, speedup {}",
+        stats.files_transferred,
+        stats.files_created,
+        stats.files_deleted,
+        format_size(stats.total_transferred_size),
+        format_size(stats.total_size),
+        stats.speedup
+    )
+}
+
+/// Why a backup was skipped, for the summary
+fn skip_reason(report: &Report<CheckError>) -> String {
+    if let Some(problem) = report.downcast_ref::<MountProblem>() {
+        return problem.to_string();
+    }
+    match report.downcast_ref::<SpaceProblem>() {
+        Some(problem) => problem.to_string(),
+        None => String::from("Pre-flight checks failed"),
+    }
+}
+
+fn selection(select: opt::Select) -> Selection {
+    Selection {
+        only: select.names.into_iter().chain(select.only).collect(),
+        skip: select.skip,
+        tags: select.tag,
+        exclude_tags: select.exclude_tag,
+    }
+}
+
+/// A backup counts as failed if it couldn't run,
+/// rsync exited with an unaccepted code, the logs couldn't be saved
+/// or `latest` couldn't be pointed to the new snapshot.
+fn is_failure<C>(runner: &Backup, result: &Result<CommandOutput, C>) -> bool {
+    match result {
+        Ok(output) => {
+            runner.classify(&output.status) == Outcome::Fail
+                || output.log_result.is_err()
+                || output.link_result.is_err()
+        }
+        Err(_) => true,
+    }
+}
+
+fn preview(config_path: Option<&Path>) -> Result<(), FatalError> {
+    let config = load_config(config_path)?;
+    let template = config.build_template().change_context(FatalError)?;
+    let (settings, shared_context, runners) = config.build().change_context(FatalError)?;
+    let style = settings.preview.style();
+    let interactive = settings.preview.interactive;
+
+    template.preview_template(&shared_context, &style);
+    for (i, runner) in runners.iter().enumerate() {
+        if interactive && !wait_for_continue() {
+            break;
+        }
+        println!();
+
+        let name = runner.label(&format!("{}/{}", i + 1, runners.len()));
+        runner.preview(&shared_context, &name, &style, &template);
+    }
+
+    Ok(())
+}
+
+/// Report every problem with the config file, instead of stopping at the first
+fn check(config_path: Option<&Path>) -> Result<i32, FatalError> {
+    let path = locate_config(config_path).change_context(FatalError)?;
+    let config = read_config(&path).change_context(FatalError)?;
+    let parsed = Config::parse(&config);
+    let style = match &parsed {
+        Ok((config, _)) => config.settings().run.style(),
+        Err(_) => Settings::default().run.style(),
+    };
+    println!("Checking {}\n", path.display());
+
+    let mut problems = Vec::new();
+    let mut warnings = Vec::new();
+    match parsed {
+        Err(e) => problems.push(format!("{e:?}")),
+        Ok((config, unknown)) => {
+            match config.strict() {
+                true => problems.extend(unknown.iter().map(UnknownKey::to_string)),
+                false => warnings.extend(unknown.iter().map(UnknownKey::to_string)),
+            }
+            if let Err(e) = config.build_template() {
+                problems.push(format!("Template:\n{e:?}"));
+            }
+            let spans = config.key_spans();
+            let shared_context = config
+                .build_context()
+                .map_err(|e| problems.push(format!("{e:?}")))
+                .ok();
+            // Every backup is built and checked on its own,
+            // so a broken one doesn't hide the problems of the others
+            let results = config.build_backups();
+            let total = results.len();
+            let mut runners = Vec::with_capacity(total);
+            for (i, result) in results.into_iter().enumerate() {
+                match result {
+                    Ok(runner) => runners.push((i, runner)),
+                    Err(e) => problems.extend(
+                        e.iter()
+                            .map(|e| format!("Backup {}/{}:\n{e:?}", i + 1, total)),
+                    ),
+                }
+            }
+            if let Some(shared_context) = &shared_context {
+                let built = runners.iter().map(|(i, runner)| (*i, runner));
+                problems.extend(
+                    validate_backups(shared_context, built)
+                        .into_iter()
+                        .map(|e| format!("{e:?}")),
+                );
+                for (i, runner) in &runners {
+                    let num = runner.label(&format!("{}/{}", i + 1, total));
+                    let tables = [&format!("run[{}]", i), "template"];
+                    problems.extend(
+                        runner
+                            .check(shared_context)
+                            .into_iter()
+                            .map(|e| spans.locate(e, &tables))
+                            .map(|e| format!("Backup {}:\n{e:?}", num)),
+                    );
+                }
+            }
+        }
+    }
+
+    for warning in &warnings {
+        println!(
+            "{}: {}\n",
+            style.paint(Outcome::Warn, Color::Yellow),
+            warning
+        );
+    }
+    if problems.is_empty() {
+        println!(
+            "{}: No problems found",
+            style.paint(Outcome::Ok, Color::Green)
+        );
+        return Ok(EXIT_OK);
+    }
+    for problem in &problems {
+        println!("{}: {}\n", style.paint(Outcome::Fail, Color::Red), problem);
+    }
+    println!("{} problem(s) found", problems.len());
+    Ok(EXIT_FAILED)
+}
+
+/// Edit a temporary copy of the config file
+/// and only replace the original once the copy is valid.
+fn configure(config_path: Option<&Path>, editor: Option<String>) -> Result<i32, FatalError> {
+    let path = match config_path {
+        Some(path) => expand_home(path),
+        None => locate_config(None)
+            .or_else(|_| default_config_path())
+            .change_context(FatalError)?,
+    };
+    let original = match path.exists() {
+        true => read_config(&path).change_context(FatalError)?,
+        false => DEFAULT_CONFIG.to_owned(),
+    };
+
+    let editor = editor
+        .or_else(|| env::var(VISUAL_VARIABLE).ok())
+        .or_else(|| env::var(EDITOR_VARIABLE).ok())
+        .filter(|e| !e.trim().is_empty())
+        .unwrap_or(DEFAULT_EDITOR.to_owned());
+
+    let temp = write_temp_config(&original).change_context(FatalError)?;
+    let result = edit_until_valid(&editor, &temp);
+    let _ = remove_file(&temp);
+
+    match result? {
+        Some(edited) if edited == original && path.exists() => {
+            println!("No changes made to {}", path.display());
+            Ok(EXIT_OK)
+        }
+        Some(edited) => {
+            write_config(&path, &edited).change_context(FatalError)?;
+            println!("Saved {}", path.display());
+            Ok(EXIT_OK)
+        }
+        None => {
+            println!("Discarded changes, {} was left untouched", path.display());
+            Ok(EXIT_FAILED)
+        }
+    }
+}
+
+/// Open `temp` in `editor` until it contains a valid config.
+/// Returns `None` if the user decided to discard their changes.
+fn edit_until_valid(editor: &str, temp: &Path) -> Result<Option<String>, FatalError> {
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or(DEFAULT_EDITOR);
+    let args = Vec::from_iter(words);
+
+    loop {
+        let status = Command::new(program)
+            .args(&args)
+            .arg(temp)
+            .status()
+            .change_context(FatalError)
+            .attach_printable_lazy(|| format!("Failed to open editor {:?}", editor))?;
+        if !status.success() {
+            return Err(Report::new(FatalError)
+                .attach_printable(format!("Editor {:?} exited with {}", editor, status)));
+        }
+
+        let edited = read_config(temp).change_context(FatalError)?;
+        let Err(e) = validate(&edited) else {
+            return Ok(Some(edited));
+        };
+
+        eprintln!("{e:?}");
+        loop {
+            match prompt("The config is invalid. [e]dit again or [d]iscard changes?") {
+                Some(a) if a.eq_ignore_ascii_case("e") => break,
+                Some(a) if a.eq_ignore_ascii_case("d") => return Ok(None),
+                Some(_) => continue,
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+/// Check that a config can be parsed and built
+fn validate(config: &str) -> Result<(), FatalError> {
+    let config = Config::from_str(config).change_context(FatalError)?;
+    config.build_template().change_context(FatalError)?;
+    config.build().change_context(FatalError)?;
     Ok(())
 }
 
+/// Locate and parse the config file, warning about tolerated unknown keys
+fn load_config(config_path: Option<&Path>) -> Result<Config, FatalError> {
+    let path = locate_config(config_path).change_context(FatalError)?;
+    let config = read_config(&path).change_context(FatalError)?;
+    let config = Config::from_str(&config).change_context(FatalError)?;
+    for key in config.unknown_keys() {
+        eprintln!("Warning: {}", key);
+    }
+    Ok(config)
+}
+
 fn check_rsync_exists() {
     let output = Command::new("rsync").arg("--version").output();
     if let Err(e) = output {
         eprintln!("Not able to execute rsync!\n=> {}", e);
-        exit(1);
+        exit(EXIT_FATAL);
     }
 }
@@ -1,14 +1,18 @@
 use std::path::PathBuf;
 
-use clap::{command, Parser};
+use clap::{Args, Parser};
 
 /// A configurable rsync wrapper with inbuilt logging
 #[derive(Clone, Debug, Parser)]
 #[command(author, version, about, long_about=None)]
 pub struct Opt {
     /// Path to config file
-    #[arg(short, long, default_value = "~/.config/backer.toml")]
-    config: PathBuf,
+    ///
+    /// Defaults to the first existing file out of `$BACKER_CONFIG`,
+    /// `$XDG_CONFIG_HOME/backer/backer.toml`, `~/.config/backer.toml`
+    /// and `/etc/backer.toml`
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
 
     #[command(subcommand)]
     pub command: Command,
@@ -17,14 +21,95 @@ pub struct Opt {
 #[derive(Clone, Debug, Parser)]
 pub enum Command {
     /// Run configured backups
-    Run,
+    Run {
+        #[command(flatten)]
+        select: Select,
+    },
+
+    /// List configured backups
+    List {
+        #[command(flatten)]
+        select: Select,
+    },
 
     /// Preview configured backups
     Preview,
 
-    /// Open config file
+    /// Compare backups with their source by checksum, without copying anything
+    Verify {
+        #[command(flatten)]
+        select: Select,
+    },
+
+    /// Delete snapshots the retention policy doesn't keep
+    Prune {
+        #[command(flatten)]
+        select: Select,
+
+        /// Only list the snapshots that would be deleted
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Copy a backup back to its source or another destination
+    ///
+    /// Shows a dry run first and asks before restoring anything
+    Restore {
+        /// Name of the backup to restore
+        name: String,
+
+        /// Snapshot to restore, by name or date prefix, defaults to the latest
+        #[arg(long, value_name = "DATE")]
+        snapshot: Option<String>,
+
+        /// Restore to this directory instead of the backup's source
+        #[arg(long, value_name = "PATH")]
+        to: Option<PathBuf>,
+
+        /// Only restore this path, relative to the backup's source
+        #[arg(long, value_name = "SUBPATH")]
+        path: Option<PathBuf>,
+
+        /// Delete files in the destination that aren't in the backup
+        #[arg(long)]
+        delete: bool,
+
+        /// Restore without a dry run and confirmation first
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Check config file for errors without running anything
+    #[command(alias = "validate")]
+    Check,
+
+    /// Edit config file and validate it afterwards
     Configure {
-        #[arg(short, long, default_value = "vi")]
-        editor: String,
+        /// Editor to use, defaults to `$VISUAL`, `$EDITOR` or `vi`
+        #[arg(short, long)]
+        editor: Option<String>,
     },
 }
+
+/// Arguments for choosing which backups to act on
+#[derive(Clone, Debug, Args)]
+pub struct Select {
+    /// Names of the backups to select, selects all if none are given
+    pub names: Vec<String>,
+
+    /// Only select the backup with this name
+    #[arg(long, value_name = "NAME")]
+    pub only: Vec<String>,
+
+    /// Don't select the backup with this name
+    #[arg(long, value_name = "NAME")]
+    pub skip: Vec<String>,
+
+    /// Only select backups with this tag
+    #[arg(long, value_name = "TAG")]
+    pub tag: Vec<String>,
+
+    /// Don't select backups with this tag
+    #[arg(long, value_name = "TAG")]
+    pub exclude_tag: Vec<String>,
+}
@@ -0,0 +1,313 @@
+use std::{fmt, process::ExitStatus};
+
+/// Names and meanings of rsync's exit codes, as documented in `man rsync`
+const EXIT_CODES: &[(i32, &str, &str)] = &[
+    (0, "success", "Success"),
+    (1, "syntax", "Syntax or usage error"),
+    (2, "protocol", "Protocol incompatibility"),
+    (
+        3,
+        "file-select",
+        "Errors selecting input/output files, dirs",
+    ),
+    (4, "unsupported", "Requested action not supported"),
+    (5, "start-client", "Error starting client-server protocol"),
+    (6, "log-append", "Daemon unable to append to log-file"),
+    (10, "socket-io", "Error in socket I/O"),
+    (11, "file-io", "Error in file I/O"),
+    (12, "stream", "Error in rsync protocol data stream"),
+    (13, "diagnostics", "Errors with program diagnostics"),
+    (14, "ipc", "Error in IPC code"),
+    (20, "signal", "Received SIGUSR1 or SIGINT"),
+    (21, "waitpid", "Some error returned by waitpid()"),
+    (22, "memory", "Error allocating core memory buffers"),
+    (23, "partial", "Partial transfer due to error"),
+    (
+        24,
+        "vanished",
+        "Partial transfer due to vanished source files",
+    ),
+    (25, "max-delete", "The --max-delete limit stopped deletions"),
+    (30, "timeout", "Timeout in data send/receive"),
+    (
+        35,
+        "daemon-timeout",
+        "Timeout waiting for daemon connection",
+    ),
+];
+
+/// Length of the `YXcstpoguax` code in front of itemized changes
+const ITEM_CODE_LENGTH: usize = 11;
+
+/// Makes rsync print transfer statistics when it's done
+pub(crate) const ARG_STATS: &str = "--stats";
+/// Makes rsync print its overall progress
+pub(crate) const ARG_PROGRESS: &str = "--info=progress2";
+/// Makes rsync scan all files before transferring, so its progress is accurate
+pub(crate) const ARG_NO_INC_RECURSIVE: &str = "--no-inc-recursive";
+/// Makes rsync print a line for every changed file
+pub(crate) const ARG_ITEMIZE_CHANGES: &str = "--itemize-changes";
+
+/// Exit codes that don't fail a backup by default
+pub(crate) const DEFAULT_ACCEPT_CODES: &[i32] = &[0];
+/// Exit codes that only warn by default
+pub(crate) const DEFAULT_WARN_CODES: &[i32] = &[24];
+
+/// Name and meaning of an rsync exit code
+pub(crate) fn exit_code_info(code: i32) -> Option<(&'static str, &'static str)> {
+    EXIT_CODES
+        .iter()
+        .find(|(c, _, _)| *c == code)
+        .map(|(_, name, meaning)| (*name, *meaning))
+}
+
+/// Human readable description of how rsync exited
+pub(crate) fn describe_status(status: &ExitStatus) -> String {
+    match status.code() {
+        Some(code) => match exit_code_info(code) {
+            Some((name, meaning)) => format!("exit code {} ({}): {}", code, name, meaning),
+            None => format!("exit code {}: Unknown error", code),
+        },
+        None => status.to_string(),
+    }
+}
+
+/// Statistics rsync prints with `--stats`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct TransferStats {
+    pub files_transferred: u64,
+    pub files_created: u64,
+    pub files_deleted: u64,
+    pub total_size: u64,
+    pub total_transferred_size: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub speedup: f64,
+}
+
+impl TransferStats {
+    /// Parse the statistics out of rsync's output, or `None` if there aren't any.
+    /// Statistics older versions of rsync don't print are left at `0`.
+    pub(crate) fn parse(output: &str) -> Option<TransferStats> {
+        let value = |labels: &[&str]| {
+            let value = output.lines().find_map(|line| {
+                let line = line.trim();
+                labels.iter().find_map(|label| line.strip_prefix(label))
+            })?;
+            value
+                .split_whitespace()
+                .next()?
+                .replace(',', "")
+                .parse()
+                .ok()
+        };
+        let speedup = output
+            .lines()
+            .find_map(|line| line.split_once("speedup is "))
+            .and_then(|(_, s)| s.split_whitespace().next()?.replace(',', "").parse().ok());
+
+        Some(TransferStats {
+            files_transferred: value(&[
+                "Number of regular files transferred:",
+                "Number of files transferred:",
+            ])
+            .unwrap_or_default(),
+            files_created: value(&["Number of created files:"]).unwrap_or_default(),
+            files_deleted: value(&["Number of deleted files:"]).unwrap_or_default(),
+            total_size: value(&["Total file size:"]).unwrap_or_default(),
+            total_transferred_size: value(&["Total transferred file size:"])?,
+            bytes_sent: value(&["Total bytes sent:"]).unwrap_or_default(),
+            bytes_received: value(&["Total bytes received:"]).unwrap_or_default(),
+            speedup: speedup.unwrap_or_default(),
+        })
+    }
+
+    /// Name and value of each statistic, like `("files_transferred", "3")`
+    pub(crate) fn fields(&self) -> [(&'static str, String); 8] {
+        [
+            ("files_transferred", self.files_transferred.to_string()),
+            ("files_created", self.files_created.to_string()),
+            ("files_deleted", self.files_deleted.to_string()),
+            ("total_size", self.total_size.to_string()),
+            (
+                "total_transferred_size",
+                self.total_transferred_size.to_string(),
+            ),
+            ("bytes_sent", self.bytes_sent.to_string()),
+            ("bytes_received", self.bytes_received.to_string()),
+            ("speedup", self.speedup.to_string()),
+        ]
+    }
+}
+
+/// An update of rsync's overall progress, printed with `--info=progress2`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Progress {
+    /// Bytes transferred so far
+    pub bytes: u64,
+    pub percent: u8,
+    /// Transfer rate, like `12.34MB/s`
+    pub rate: String,
+    /// Estimated time remaining, or the total time once done
+    pub eta: String,
+}
+
+impl Progress {
+    /// Parse a line like `1,234,567  45%   12.34MB/s    0:00:12 (xfr#3, to-chk=10/100)`
+    pub(crate) fn parse(line: &str) -> Option<Progress> {
+        let mut words = line.split_whitespace();
+        let bytes = words.next()?.replace(',', "").parse().ok()?;
+        let percent = words.next()?.strip_suffix('%')?.parse().ok()?;
+        let rate = words.next().filter(|r| r.ends_with("/s"))?;
+        let eta = words.next().filter(|e| e.contains(':'))?;
+        Some(Progress {
+            bytes,
+            percent,
+            rate: rate.to_owned(),
+            eta: eta.to_owned(),
+        })
+    }
+}
+
+/// How a file differs between source and target,
+/// as reported by `--itemize-changes`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Change {
+    /// Missing from the target
+    Created,
+    /// Content differs
+    Updated,
+    /// Only in the target, and deleted because of `--delete`
+    Deleted,
+    /// Only attributes like times or permissions differ
+    Attributes,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Created => f.pad("created"),
+            Change::Updated => f.pad("updated"),
+            Change::Deleted => f.pad("deleted"),
+            Change::Attributes => f.pad("attributes"),
+        }
+    }
+}
+
+/// A line of rsync's `--itemize-changes` output
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Item {
+    pub change: Change,
+    pub directory: bool,
+    pub path: String,
+}
+
+impl Item {
+    /// Parse a line like `>f.st...... docs/report.odt` or `*deleting   old.txt`,
+    /// returning `None` for any other output
+    pub(crate) fn parse(line: &str) -> Option<Item> {
+        if let Some(path) = line.strip_prefix("*deleting") {
+            let path = path.trim_start();
+            return Some(Item {
+                change: Change::Deleted,
+                directory: path.ends_with('/'),
+                path: path.to_owned(),
+            });
+        }
+
+        let (code, path) = line.split_at_checked(ITEM_CODE_LENGTH)?;
+        let path = path.strip_prefix(' ')?;
+        let mut flags = code.chars();
+        let update = flags.next()?;
+        let kind = flags.next()?;
+        if !"<>ch.".contains(update) || !"fdLDS".contains(kind) || path.is_empty() {
+            return None;
+        }
+        // Symlinks are listed with their destination
+        let path = match kind {
+            'L' => path.split(" -> ").next().unwrap_or(path),
+            _ => path,
+        };
+        let attributes = flags.as_str();
+        let change = match update {
+            _ if attributes.chars().all(|c| c == '+') => Change::Created,
+            '.' => Change::Attributes,
+            _ => Change::Updated,
+        };
+        Some(Item {
+            change,
+            directory: kind == 'd',
+            path: path.to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<10} {}", self.change, self.path)
+    }
+}
+
+/// All changes in the output of `--itemize-changes`
+pub(crate) fn itemized_changes(output: &str) -> Vec<Item> {
+    output.lines().filter_map(Item::parse).collect()
+}
+
+/// How many files and directories a backup changed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct ChangeCounts {
+    /// Files missing from the target before
+    pub created: usize,
+    /// Files with changed content
+    pub updated: usize,
+    /// Files deleted from the target
+    pub deleted: usize,
+    /// Files and directories with only changed attributes
+    pub attributes: usize,
+    /// Directories created in or deleted from the target
+    pub directories: usize,
+}
+
+impl ChangeCounts {
+    pub(crate) fn add(&mut self, item: &Item) {
+        let count = match (item.change, item.directory) {
+            (Change::Attributes, _) => &mut self.attributes,
+            (_, true) => &mut self.directories,
+            (Change::Created, false) => &mut self.created,
+            (Change::Updated, false) => &mut self.updated,
+            (Change::Deleted, false) => &mut self.deleted,
+        };
+        *count += 1;
+    }
+}
+
+impl fmt::Display for ChangeCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} new, {} updated, {} deleted, {} with changed attributes, {} directories",
+            self.created, self.updated, self.deleted, self.attributes, self.directories
+        )
+    }
+}
+
+/// Classification of a finished backup
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Outcome {
+    Ok,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Ok => write!(f, "OK"),
+            Outcome::Warn => write!(f, "WARN"),
+            Outcome::Fail => write!(f, "FAIL"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,108 @@
+use super::*;
+
+#[test]
+fn parsing_items() {
+    let output = "\
+sending incremental file list
+cd+++++++++ new/
+>f+++++++++ new/file.txt
+>f.st...... changed.txt
+.d..t...... ./
+.f...p..... mode.txt
+cL+++++++++ link -> file.txt
+*deleting   old file.txt
+
+sent 1,234 bytes  received 56 bytes";
+
+    let item = |change, path: &str| Item {
+        change,
+        directory: path.ends_with('/'),
+        path: path.to_owned(),
+    };
+    let items = itemized_changes(output);
+    assert_eq!(
+        items,
+        vec![
+            item(Change::Created, "new/"),
+            item(Change::Created, "new/file.txt"),
+            item(Change::Updated, "changed.txt"),
+            item(Change::Attributes, "./"),
+            item(Change::Attributes, "mode.txt"),
+            item(Change::Created, "link"),
+            item(Change::Deleted, "old file.txt"),
+        ]
+    );
+    let mut counts = ChangeCounts::default();
+    items.iter().for_each(|item| counts.add(item));
+    assert_eq!(
+        counts,
+        ChangeCounts {
+            created: 2,
+            updated: 1,
+            deleted: 1,
+            attributes: 2,
+            directories: 1,
+        }
+    );
+    assert_eq!(items[2].to_string(), "updated    changed.txt");
+}
+
+#[test]
+fn parsing_stats() {
+    let output = "\
+sending incremental file list
+docs/report.odt
+
+Number of files: 1,204 (reg: 1,100, dir: 104)
+Number of created files: 12 (reg: 10, dir: 2)
+Number of deleted files: 3 (reg: 3)
+Number of regular files transferred: 25
+Total file size: 5,368,709,120 bytes
+Total transferred file size: 1,234,567 bytes
+Literal data: 1,234,567 bytes
+Matched data: 0 bytes
+Total bytes sent: 1,240,000
+Total bytes received: 512
+
+sent 1,240,000 bytes  received 512 bytes  826,674.67 bytes/sec
+total size is 5,368,709,120  speedup is 4,327.83 (DRY RUN)";
+
+    assert_eq!(
+        TransferStats::parse(output),
+        Some(TransferStats {
+            files_transferred: 25,
+            files_created: 12,
+            files_deleted: 3,
+            total_size: 5_368_709_120,
+            total_transferred_size: 1_234_567,
+            bytes_sent: 1_240_000,
+            bytes_received: 512,
+            speedup: 4327.83,
+        })
+    );
+
+    let old = "Number of files transferred: 2\nTotal transferred file size: 10 bytes";
+    let stats = TransferStats::parse(old).unwrap();
+    assert_eq!(stats.files_transferred, 2);
+    assert_eq!(stats.total_transferred_size, 10);
+    assert_eq!(TransferStats::parse("sending incremental file list"), None);
+}
+
+#[test]
+fn parsing_progress() {
+    assert_eq!(
+        Progress::parse("    1,234,567  45%   12.34MB/s    0:00:12 (xfr#3, to-chk=10/100)"),
+        Some(Progress {
+            bytes: 1_234_567,
+            percent: 45,
+            rate: String::from("12.34MB/s"),
+            eta: String::from("0:00:12"),
+        })
+    );
+    assert_eq!(
+        Progress::parse("          0   0%    0.00kB/s    0:00:00").map(|p| p.percent),
+        Some(0)
+    );
+    assert_eq!(Progress::parse("sending incremental file list"), None);
+    assert_eq!(Progress::parse("1234 files to consider"), None);
+}
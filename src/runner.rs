@@ -1,31 +1,245 @@
 use std::{
+    collections::VecDeque,
+    fmt,
     io::{BufRead, BufReader},
     path::Path,
     process::{Command, ExitStatus, Stdio},
-    sync::mpsc::channel,
+    sync::{Mutex, MutexGuard, PoisonError},
     thread,
 };
 
 use error_stack::{Context, Report, Result, ResultExt};
 
 use crate::{
-    fs::{save, SaveLogError},
+    fs::{LinkError, LogFile, SaveLogError},
+    rsync::{ChangeCounts, Item, Progress, TransferStats},
     runner::error::ThreadError,
 };
 
-use self::error::CommandRunError;
+use self::{
+    error::CommandRunError,
+    progress::{read_updates, ProgressLine},
+};
 
 mod error;
+mod progress;
+
+/// How many of the last lines of stdout to keep, enough to hold rsync's statistics
+const STATS_LINES: usize = 64;
+
+/// Exit status of a command and a summary of its output,
+/// alongside the result of saving the logs
+pub(crate) struct CommandOutput {
+    pub status: ExitStatus,
+    /// Number of lines the command printed to stdout, without progress updates
+    pub stdout_lines: usize,
+    /// Number of lines the command printed to stderr
+    pub stderr_lines: usize,
+    /// Transfer statistics, if they were requested and rsync printed them
+    pub stats: Option<TransferStats>,
+    /// How many files changed, if itemized changes were requested
+    pub changes: Option<ChangeCounts>,
+    pub log_result: Result<(), SaveLogError>,
+    /// Result of pointing `latest` to a new snapshot, left to the caller
+    pub link_result: Result<(), LinkError>,
+}
+
+/// What to do with the output of a command
+pub(crate) struct RunOptions<'a> {
+    /// Where to save stdout to, if anywhere
+    pub stdout: Option<&'a str>,
+    /// Where to save stderr to, if anywhere
+    pub stderr: Option<&'a str>,
+    /// Where to save stdout and stderr together, in the order lines arrive
+    pub combined: Option<&'a str>,
+    /// Where to list every changed file, if anywhere
+    pub changes: Option<&'a str>,
+    /// Written to every log before the output of the command,
+    /// or `None` to leave the output out of the logs
+    pub header: Option<&'a str>,
+    /// Append to the logs instead of overwriting them
+    pub append: bool,
+    /// Parse the output of rsync's `--stats`
+    pub stats: bool,
+    /// Parse the output of rsync's `--itemize-changes`
+    pub itemize: bool,
+    /// Show the output of rsync's `--info=progress2` on a single line
+    pub progress: bool,
+}
 
-pub(crate) fn run_command<F, E>(
+/// Which output of the command a line was printed to
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl fmt::Display for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stream::Stdout => write!(f, "stdout"),
+            Stream::Stderr => write!(f, "stderr"),
+        }
+    }
+}
+
+/// A log being written while the command runs.
+///
+/// Lines are still counted after writing one failed, the first error is kept.
+struct Log {
+    file: Option<LogFile>,
+    result: Result<(), SaveLogError>,
+    /// Whether lines are written, or only counted
+    output: bool,
+    lines: usize,
+}
+
+impl Log {
+    fn open(path: &Path, append: bool, header: Option<&str>) -> Log {
+        let file = LogFile::create(path, append).and_then(|mut file| {
+            file.write(header.unwrap_or_default())?;
+            Ok(file)
+        });
+        let (file, result) = match file {
+            Ok(file) => (Some(file), Ok(())),
+            Err(e) => (None, Err(e)),
+        };
+        Log {
+            file,
+            result,
+            output: header.is_some(),
+            lines: 0,
+        }
+    }
+
+    /// A log that only counts lines
+    fn discard() -> Log {
+        Log {
+            file: None,
+            result: Ok(()),
+            output: false,
+            lines: 0,
+        }
+    }
+
+    /// Lines are separated, not terminated, by newlines,
+    /// so the footer continues right after the last line
+    fn write_line(&mut self, line: &str) {
+        if let (Some(file), true) = (&mut self.file, self.output) {
+            let result = match self.lines {
+                0 => file.write(line),
+                _ => file.write("\n").and_then(|_| file.write(line)),
+            };
+            if let Err(e) = result {
+                self.file = None;
+                self.result = Err(e);
+            }
+        }
+        self.lines += 1;
+    }
+
+    fn fail(&mut self, error: Report<SaveLogError>) {
+        if self.result.is_ok() {
+            self.result = Err(error);
+        }
+    }
+
+    fn finish(mut self, footer: &str) -> (usize, Result<(), SaveLogError>) {
+        if let Some(file) = &mut self.file {
+            if let Err(e) = file.write(footer).and_then(|_| file.write("\n")) {
+                self.result = Err(e);
+            }
+        }
+        (self.lines, self.result)
+    }
+}
+
+/// Writes every line of one stream to its log and the combined log
+struct Logger<'a, M> {
+    /// Formats a line, given its stream and line number
+    format: M,
+    combined: Option<&'a Mutex<Log>>,
+    /// Whether the output is logged at all
+    output: bool,
+}
+
+impl<M, E> Logger<'_, M>
+where
+    M: FnMut(Stream, usize, &str) -> Result<String, E>,
+    E: Context,
+{
+    /// Format a line as it arrives, logging it as is if that fails
+    fn write(&mut self, log: &mut Log, stream: Stream, line: &str) {
+        if !self.output {
+            log.write_line(line);
+            return;
+        }
+        let formatted = (self.format)(stream, log.lines + 1, line).unwrap_or_else(|e| {
+            log.fail(e.change_context(SaveLogError));
+            line.to_owned()
+        });
+        log.write_line(&formatted);
+        if let Some(combined) = self.combined {
+            lock(combined).write_line(&formatted);
+        }
+    }
+}
+
+/// A poisoned lock only means the other thread panicked, which is reported anyway
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Everything gathered from stdout while streaming it to the log
+struct Stdout {
+    log: Log,
+    /// The last lines of output, if statistics were requested
+    tail: Option<VecDeque<String>>,
+    changes: Option<ChangeCounts>,
+    change_log: Option<Log>,
+}
+
+impl Stdout {
+    fn record<M, E>(&mut self, line: &str, logger: &mut Logger<M>)
+    where
+        M: FnMut(Stream, usize, &str) -> Result<String, E>,
+        E: Context,
+    {
+        logger.write(&mut self.log, Stream::Stdout, line);
+        if let Some(tail) = &mut self.tail {
+            if tail.len() == STATS_LINES {
+                tail.pop_front();
+            }
+            tail.push_back(line.to_owned());
+        }
+        let Some(counts) = &mut self.changes else {
+            return;
+        };
+        if let Some(item) = Item::parse(line) {
+            counts.add(&item);
+            if let Some(change_log) = &mut self.change_log {
+                change_log.write_line(&item.to_string());
+            }
+        }
+    }
+}
+
+/// Run an rsync command, writing its output to the logs as it arrives.
+///
+/// Each log starts with `options.header` and ends with the `footer`,
+/// which can depend on how the command exited.
+/// `line_format` creates a formatter for each stream,
+/// which formats every line of it as it arrives.
+pub(crate) fn run_command<L, M, F, E>(
     mut command: Command,
-    save_stdout: &str,
-    save_stderr: &str,
-    append: bool,
-    formatter: F,
-) -> Result<((ExitStatus, String, String), Result<(), SaveLogError>), CommandRunError>
+    options: &RunOptions,
+    line_format: L,
+    footer: F,
+) -> Result<CommandOutput, CommandRunError>
 where
-    F: Fn(&str) -> Result<String, E>,
+    L: Fn() -> M + Sync,
+    M: FnMut(Stream, usize, &str) -> Result<String, E>,
+    F: Fn(&ExitStatus, Option<&TransferStats>) -> Result<String, E>,
     E: Context,
 {
     let mut child = command
@@ -38,48 +252,123 @@ where
     let stdout = child.stdout.take().ok_or(Report::new(CommandRunError))?;
     let stderr = child.stderr.take().ok_or(Report::new(CommandRunError))?;
 
-    let (out_tx, out_rx) = channel();
-    let (err_tx, err_rx) = channel();
+    let append = options.append;
+    let open = |path: Option<&str>| match path {
+        Some(path) => Log::open(Path::new(path), append, options.header),
+        None => Log::discard(),
+    };
+    let mut out = Stdout {
+        log: open(options.stdout),
+        tail: options.stats.then(VecDeque::new),
+        changes: options.itemize.then(ChangeCounts::default),
+        change_log: options
+            .changes
+            .map(|path| Log::open(Path::new(path), append, Some(""))),
+    };
+    let mut err_log = open(options.stderr);
+    let combined = options.combined.map(|path| Mutex::new(open(Some(path))));
 
-    let out_thread = thread::spawn(move || -> Result<(), ThreadError> {
-        let stdout_lines = BufReader::new(stdout).lines();
-        for line in stdout_lines {
-            let line = line.change_context(ThreadError)?;
-            println!("{}", line);
-            out_tx.send(line).change_context(ThreadError)?;
-        }
-        Ok(())
-    });
-
-    let err_thread = thread::spawn(move || -> Result<(), ThreadError> {
-        let stderr_lines = BufReader::new(stderr).lines();
-        for line in stderr_lines {
-            let line = line.change_context(ThreadError)?;
-            eprintln!("{}", line);
-            err_tx.send(line).change_context(ThreadError)?;
-        }
-        Ok(())
-    });
+    let logger = || Logger {
+        format: line_format(),
+        combined: combined.as_ref(),
+        output: options.header.is_some(),
+    };
+    // Shared, so stderr doesn't get glued to the progress line either
+    let display = options.progress.then(|| Mutex::new(ProgressLine::new()));
+    let display = display.as_ref();
+    let (status, out, err_log) = thread::scope(|scope| -> Result<_, CommandRunError> {
+        let out_thread = scope.spawn(|| -> Result<Stdout, ThreadError> {
+            let mut logger = logger();
+            let stdout = BufReader::new(stdout);
+            if let Some(display) = display {
+                // Progress updates are shown, but left out of the log
+                read_updates(stdout, |line| {
+                    match Progress::parse(line) {
+                        Some(progress) => lock(display).update(&progress),
+                        None => {
+                            lock(display).println(line);
+                            out.record(line, &mut logger);
+                        }
+                    }
+                    Ok(())
+                })?;
+                lock(display).finish();
+                return Ok(out);
+            }
+            for line in stdout.lines() {
+                let line = line.change_context(ThreadError)?;
+                println!("{}", line);
+                out.record(&line, &mut logger);
+            }
+            Ok(out)
+        });
 
-    let status = child.wait().change_context(CommandRunError)?;
+        let err_thread = scope.spawn(|| -> Result<Log, ThreadError> {
+            let mut logger = logger();
+            let stderr_lines = BufReader::new(stderr).lines();
+            for line in stderr_lines {
+                let line = line.change_context(ThreadError)?;
+                match display {
+                    Some(display) => lock(display).eprintln(&line),
+                    None => eprintln!("{}", line),
+                }
+                logger.write(&mut err_log, Stream::Stderr, &line);
+            }
+            Ok(err_log)
+        });
 
-    out_thread
-        .join()
-        .unwrap_or(Err(Report::new(ThreadError)))
-        .change_context(CommandRunError)?;
-    err_thread
-        .join()
-        .unwrap_or(Err(Report::new(ThreadError)))
-        .change_context(CommandRunError)?;
+        let status = child.wait().change_context(CommandRunError)?;
 
-    let stdout = out_rx.into_iter().collect::<Vec<String>>().join("\n");
-    let stderr = err_rx.into_iter().collect::<Vec<String>>().join("\n");
+        let out = out_thread
+            .join()
+            .unwrap_or(Err(Report::new(ThreadError)))
+            .change_context(CommandRunError)?;
+        let err_log = err_thread
+            .join()
+            .unwrap_or(Err(Report::new(ThreadError)))
+            .change_context(CommandRunError)?;
+        Ok((status, out, err_log))
+    })?;
 
-    let stdout = formatter(&stdout).change_context(CommandRunError)?;
-    let stderr = formatter(&stderr).change_context(CommandRunError)?;
+    let stats = out
+        .tail
+        .and_then(|tail| TransferStats::parse(&Vec::from(tail).join("\n")));
 
-    let log_result = save(&stdout, Path::new(save_stdout), append)
-        .and_then(|_| save(&stderr, Path::new(save_stderr), append));
+    // The logs are finished even if the footer can't be evaluated
+    let (footer, footer_result) = match footer(&status, stats.as_ref()) {
+        Ok(footer) => (footer, Ok(())),
+        Err(e) => (String::new(), Err(e.change_context(SaveLogError))),
+    };
+    let (stdout_lines, out_result) = out.log.finish(&footer);
+    let (stderr_lines, err_result) = err_log.finish(&footer);
+    let combined_result = match combined {
+        Some(combined) => {
+            let combined = combined
+                .into_inner()
+                .unwrap_or_else(PoisonError::into_inner);
+            combined.finish(&footer).1
+        }
+        None => Ok(()),
+    };
+    let change_result = match out.change_log {
+        Some(change_log) => change_log.finish("").1,
+        None => Ok(()),
+    };
 
-    Ok(((status, stdout, stderr), log_result))
+    Ok(CommandOutput {
+        status,
+        stdout_lines,
+        stderr_lines,
+        stats,
+        changes: out.changes,
+        log_result: footer_result
+            .and(out_result)
+            .and(err_result)
+            .and(combined_result)
+            .and(change_result),
+        link_result: Ok(()),
+    })
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,116 @@
+use std::{
+    io::{stdout, BufRead, IsTerminal, Write},
+    time::{Duration, Instant},
+};
+
+use error_stack::{Result, ResultExt};
+
+use crate::{fs::format_size, rsync::Progress};
+
+use super::error::ThreadError;
+
+/// How often progress is printed if stdout isn't a terminal
+const PLAIN_INTERVAL: Duration = Duration::from_secs(10);
+const CLEAR_LINE: &str = "\r\x1b[2K";
+
+/// Shows rsync's progress on a single updating line on a terminal,
+/// or as a plain line every few seconds otherwise
+pub(super) struct ProgressLine {
+    terminal: bool,
+    /// The progress line the terminal currently shows, if any
+    shown: Option<String>,
+    /// When and at which percentage progress was last printed as a plain line
+    printed: Option<(Instant, u8)>,
+}
+
+impl ProgressLine {
+    pub fn new() -> Self {
+        ProgressLine {
+            terminal: stdout().is_terminal(),
+            shown: None,
+            printed: None,
+        }
+    }
+
+    pub fn update(&mut self, progress: &Progress) {
+        let line = format!(
+            "{:>3}% {:>10} {:>12}  ETA {}",
+            progress.percent,
+            format_size(progress.bytes),
+            progress.rate,
+            progress.eta
+        );
+        if self.terminal {
+            print!("{}{}", CLEAR_LINE, line);
+            let _ = stdout().flush();
+            self.shown = Some(line);
+            return;
+        }
+        let due = match self.printed {
+            None => true,
+            Some((at, percent)) => {
+                at.elapsed() >= PLAIN_INTERVAL || (progress.percent == 100 && percent != 100)
+            }
+        };
+        if due {
+            println!("{}", line);
+            self.printed = Some((Instant::now(), progress.percent));
+        }
+    }
+
+    /// Print a regular line of output above the progress line
+    pub fn println(&mut self, line: &str) {
+        self.above(|| println!("{}", line));
+    }
+
+    /// Print a line of error output above the progress line
+    pub fn eprintln(&mut self, line: &str) {
+        self.above(|| eprintln!("{}", line));
+    }
+
+    /// Clear the progress line, print something and show it again below
+    fn above(&mut self, print: impl FnOnce()) {
+        let Some(shown) = &self.shown else {
+            return print();
+        };
+        print!("{}", CLEAR_LINE);
+        let _ = stdout().flush();
+        print();
+        print!("{}", shown);
+        let _ = stdout().flush();
+    }
+
+    /// Keep the last progress update visible below any further output
+    pub fn finish(&mut self) {
+        if self.shown.take().is_some() {
+            println!();
+        }
+    }
+}
+
+/// Call `f` for every line of `reader`, also splitting at the `\r`
+/// rsync ends progress updates with instead of `\n`
+pub(super) fn read_updates<R, F>(reader: R, mut f: F) -> Result<(), ThreadError>
+where
+    R: BufRead,
+    F: FnMut(&str) -> Result<(), ThreadError>,
+{
+    let mut line = Vec::new();
+    for byte in reader.bytes() {
+        match byte.change_context(ThreadError)? {
+            b'\r' if line.is_empty() => (),
+            b'\r' | b'\n' => {
+                f(&String::from_utf8_lossy(&line))?;
+                line.clear();
+            }
+            byte => line.push(byte),
+        }
+    }
+    if !line.is_empty() {
+        f(&String::from_utf8_lossy(&line))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests;
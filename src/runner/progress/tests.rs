@@ -0,0 +1,24 @@
+use super::*;
+
+#[test]
+fn reading_updates() {
+    let output = "sending incremental file list\n\r  1,024  10%  1.00MB/s  0:00:09\r  \
+        10,240 100%  1.00MB/s  0:00:10 (xfr#1, to-chk=0/1)\n\nsent 10,240 bytes";
+    let mut lines = Vec::new();
+    read_updates(output.as_bytes(), |line| {
+        lines.push(line.to_owned());
+        Ok(())
+    })
+    .unwrap();
+
+    assert_eq!(
+        lines,
+        vec![
+            "sending incremental file list",
+            "  1,024  10%  1.00MB/s  0:00:09",
+            "  10,240 100%  1.00MB/s  0:00:10 (xfr#1, to-chk=0/1)",
+            "",
+            "sent 10,240 bytes",
+        ]
+    );
+}
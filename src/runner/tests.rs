@@ -0,0 +1,133 @@
+use std::fs;
+
+use crate::fs::TempDir;
+
+use super::*;
+
+fn run(script: &str, options: &RunOptions) -> CommandOutput {
+    let mut command = Command::new("sh");
+    command.args(["-c", script]);
+    let line_format = || {
+        |stream: Stream, line_no: usize, line: &str| -> Result<String, ThreadError> {
+            Ok(format!("{} {}: {}", stream, line_no, line))
+        }
+    };
+    let footer = |status: &ExitStatus, stats: Option<&TransferStats>| {
+        let size = stats.map(|s| s.total_transferred_size.to_string());
+        Ok(format!(
+            "\nexit {} {}",
+            status.code().unwrap(),
+            size.unwrap_or_default()
+        ))
+    };
+    run_command(command, options, line_format, footer).unwrap()
+}
+
+fn options<'a>(stdout: &'a str, stderr: &'a str) -> RunOptions<'a> {
+    RunOptions {
+        stdout: Some(stdout),
+        stderr: Some(stderr),
+        combined: None,
+        changes: None,
+        header: Some("start\n"),
+        append: false,
+        stats: false,
+        itemize: false,
+        progress: false,
+    }
+}
+
+#[test]
+fn writing_logs() {
+    let dir = TempDir::new("runner-split");
+    let stdout = dir.join("out.log").to_string_lossy().into_owned();
+    let stderr = dir.join("err.log").to_string_lossy().into_owned();
+    let read = |path: &str| fs::read_to_string(path).unwrap();
+    let script = "echo a; echo b >&2; echo c; exit 3";
+
+    let output = run(script, &options(&stdout, &stderr));
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!((output.stdout_lines, output.stderr_lines), (2, 1));
+    assert!(output.log_result.is_ok());
+    assert_eq!(read(&stdout), "start\nstdout 1: a\nstdout 2: c\nexit 3 \n");
+    assert_eq!(read(&stderr), "start\nstderr 1: b\nexit 3 \n");
+
+    let appending = RunOptions {
+        append: true,
+        ..options(&stdout, &stderr)
+    };
+    run("echo d", &appending);
+    assert_eq!(
+        read(&stdout),
+        "start\nstdout 1: a\nstdout 2: c\nexit 3 \nstart\nstdout 1: d\nexit 0 \n"
+    );
+    assert_eq!(
+        read(&stderr),
+        "start\nstderr 1: b\nexit 3 \nstart\n\nexit 0 \n"
+    );
+
+    run("echo e", &options(&stdout, &stderr));
+    assert_eq!(read(&stdout), "start\nstdout 1: e\nexit 0 \n");
+    assert_eq!(read(&stderr), "start\n\nexit 0 \n");
+
+    // Without a header, the output is only counted
+    let footer_only = RunOptions {
+        header: None,
+        ..options(&stdout, &stderr)
+    };
+    let output = run("echo f; echo g", &footer_only);
+    assert_eq!(output.stdout_lines, 2);
+    assert_eq!(read(&stdout), "\nexit 0 \n");
+}
+
+#[test]
+fn combining_logs() {
+    let dir = TempDir::new("runner-combined");
+    let combined = dir.join("all.log").to_string_lossy().into_owned();
+    let options = RunOptions {
+        stdout: None,
+        stderr: None,
+        combined: Some(&combined),
+        ..options("", "")
+    };
+
+    let output = run("echo a; sleep 0.2; echo b >&2; sleep 0.2; echo c", &options);
+    assert!(output.log_result.is_ok());
+    assert_eq!(
+        fs::read_to_string(&combined).unwrap(),
+        "start\nstdout 1: a\nstderr 1: b\nstdout 2: c\nexit 0 \n"
+    );
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn summarizing_output() {
+    let dir = TempDir::new("runner-summary");
+    let stdout = dir.join("out.log").to_string_lossy().into_owned();
+    let stderr = dir.join("err.log").to_string_lossy().into_owned();
+    let changes = dir.join("changes.txt").to_string_lossy().into_owned();
+    let options = RunOptions {
+        changes: Some(&changes),
+        stats: true,
+        itemize: true,
+        ..options(&stdout, &stderr)
+    };
+
+    // More lines than are kept for the statistics come before them
+    let script = "echo '>f+++++++++ new.txt'; echo '*deleting   old/'; \
+        seq 100; echo 'Total transferred file size: 1,234 bytes'";
+    let output = run(script, &options);
+    assert_eq!(output.stdout_lines, 103);
+    assert_eq!(output.stats.unwrap().total_transferred_size, 1234);
+    assert_eq!(
+        output.changes.unwrap().to_string(),
+        "1 new, 0 updated, 0 deleted, 0 with changed attributes, 1 directories"
+    );
+    assert_eq!(
+        fs::read_to_string(&changes).unwrap(),
+        "created    new.txt\ndeleted    old/\n"
+    );
+    assert!(fs::read_to_string(&stdout)
+        .unwrap()
+        .ends_with("stdout 103: Total transferred file size: 1,234 bytes\nexit 0 1234\n"));
+}
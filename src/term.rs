@@ -0,0 +1,109 @@
+use std::{
+    env,
+    fmt::Display,
+    io::{stdin, stdout, IsTerminal, Write},
+};
+
+const NO_COLOR_VARIABLE: &str = "NO_COLOR";
+
+pub(crate) const PAGE_WIDTH: usize = 80;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Color {
+    Red,
+    Green,
+    Yellow,
+    Bold,
+    Dim,
+}
+
+impl Color {
+    fn ansi(&self) -> &'static str {
+        match self {
+            Color::Red => "\x1b[31m",
+            Color::Green => "\x1b[32m",
+            Color::Yellow => "\x1b[33m",
+            Color::Bold => "\x1b[1m",
+            Color::Dim => "\x1b[2m",
+        }
+    }
+}
+
+const ANSI_RESET: &str = "\x1b[0m";
+
+/// How output of a subcommand should be decorated
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Style {
+    pretty: bool,
+    colors: bool,
+}
+
+impl Style {
+    /// Colors are only used if stdout is a terminal and `NO_COLOR` is unset.
+    pub fn new(pretty: bool, colors: bool) -> Self {
+        Style {
+            pretty,
+            colors: colors && colors_supported(),
+        }
+    }
+
+    pub fn paint(&self, text: impl Display, color: Color) -> String {
+        match self.colors {
+            true => format!("{}{}{}", color.ansi(), text, ANSI_RESET),
+            false => text.to_string(),
+        }
+    }
+
+    /// A header introducing a new block of output
+    pub fn header(&self, title: &str) -> String {
+        if !self.pretty {
+            return format!("[{}]", self.paint(title, Color::Bold));
+        }
+
+        let taglen = title.chars().count() + 2;
+        let bar_a = "═".repeat(taglen);
+        let bar_b = "═".repeat(PAGE_WIDTH.saturating_sub(taglen + 1));
+        format!(
+            "{bar_a}╦{bar_b}\n \
+            {} ║\n\
+            {bar_a}╝",
+            self.paint(title, Color::Bold)
+        )
+    }
+
+    /// A separator between sections of the same block
+    pub fn rule(&self) -> String {
+        match self.pretty {
+            true => self.paint("─".repeat(PAGE_WIDTH), Color::Dim),
+            false => String::new(),
+        }
+    }
+}
+
+fn colors_supported() -> bool {
+    env::var_os(NO_COLOR_VARIABLE).is_none_or(|v| v.is_empty()) && stdout().is_terminal()
+}
+
+/// Wait for the user to press enter.
+/// Returns `false` if the user asked to quit instead.
+///
+/// Never blocks if stdin is not a terminal.
+pub(crate) fn wait_for_continue() -> bool {
+    if !stdin().is_terminal() {
+        return true;
+    }
+    prompt("[Enter] continue, [q] quit").is_some_and(|answer| !answer.eq_ignore_ascii_case("q"))
+}
+
+/// Ask the user a question and return the trimmed answer,
+/// or `None` if stdin was closed.
+pub(crate) fn prompt(question: &str) -> Option<String> {
+    print!("\n{}: ", question);
+    let _ = stdout().flush();
+
+    let mut answer = String::new();
+    match stdin().read_line(&mut answer) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(answer.trim().to_owned()),
+    }
+}